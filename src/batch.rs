@@ -0,0 +1,164 @@
+//! Running a program, or grading a directory of student programs, against a test spec.
+use std::{
+    fs,
+    path::{Path,PathBuf},
+    sync::{Mutex,atomic::{AtomicUsize,Ordering}},
+    thread,
+};
+use rasm::{build_code,tooling::{Spec,Outcome,run_case},Config};
+use crate::{cli::{Source,TestArgs},commands::load};
+
+struct Report {
+    student : String,
+    assembly_error : Option<String>,
+    faults : Vec<String>,
+    failures : Vec<String>,
+    passed : usize,
+}
+
+pub fn run(args : TestArgs) -> bool {
+    let spec = match Spec::load(&args.spec) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("{}",e);
+            return false
+        }
+    };
+    let dir = match &args.source {
+        Source::File(path) if path.is_dir() => path,
+        _ => return test_one(&args.source,&spec,args.config),
+    };
+    let files = match programs_in(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}: {}",dir.display(),e);
+            return false
+        }
+    };
+    let reports = grade_all(&files,&spec,args.config);
+    print_table(&reports,spec.cases.len());
+    if let Some(csv) = args.csv {
+        if let Err(e) = fs::write(&csv,to_csv(&reports,spec.cases.len())) {
+            eprintln!("{}: {}",csv.display(),e);
+            return false
+        }
+        println!("Report written to {}",csv.display());
+    }
+    true
+}
+
+fn test_one(source : &Source, spec : &Spec, config : Config) -> bool {
+    let code = match load(source) {
+        Some(code) => code,
+        None => return false,
+    };
+    let mut passed = 0;
+    for case in &spec.cases {
+        match run_case(&code,case,config) {
+            Outcome::Passed      => {passed += 1; println!("PASS  {}",case.name)},
+            Outcome::Failed(why) => println!("FAIL  {}: {}",case.name,why),
+            Outcome::Faulted(f)  => println!("FAULT {}: {}",case.name,f),
+        }
+    }
+    println!("{}/{} tests passed",passed,spec.cases.len());
+    passed == spec.cases.len()
+}
+
+fn programs_in(dir : &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|e| e.to_str()),Some("rasm") | Some("asm")) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn grade_all(files : &[PathBuf], spec : &Spec, config : Config) -> Vec<Report> {
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(files.len()));
+    let workers = thread::available_parallelism().map_or(1,|n| n.get()).min(files.len());
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1,Ordering::Relaxed);
+                let Some(file) = files.get(i) else { break };
+                let report = grade(file,spec,config);
+                reports.lock().unwrap().push((i,report));
+            });
+        }
+    });
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(i,_)| *i);
+    reports.into_iter().map(|(_,r)| r).collect()
+}
+
+fn grade(file : &Path, spec : &Spec, config : Config) -> Report {
+    let mut report = Report {
+        student : file.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        assembly_error : None,
+        faults : Vec::new(),
+        failures : Vec::new(),
+        passed : 0,
+    };
+    let code = match build_code(file) {
+        Ok(code) => code,
+        Err(e) => {
+            report.assembly_error = Some(e.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("; "));
+            return report
+        }
+    };
+    for case in &spec.cases {
+        match run_case(&code,case,config) {
+            Outcome::Passed       => report.passed += 1,
+            Outcome::Failed(why)  => report.failures.push(format!("[{}] {}",case.name,why)),
+            Outcome::Faulted(f)   => report.faults.push(format!("[{}] {}",case.name,f)),
+        }
+    }
+    report
+}
+
+fn print_table(reports : &[Report], total : usize) {
+    let width = reports.iter().map(|r| r.student.len()).chain([7]).max().unwrap();
+    println!("{:<width$} | {:^9} | {:^6} | {:^6} | Notes","Student","Assembled","Faults","Passed",width = width);
+    println!("{:-<width$}-+-{:-^9}-+-{:-^6}-+-{:-^6}-+------","","","","",width = width);
+    for r in reports {
+        let notes = match &r.assembly_error {
+            Some(e) => e.clone(),
+            None    => r.faults.iter().chain(&r.failures).cloned().collect::<Vec<_>>().join("; "),
+        };
+        println!("{:<width$} | {:^9} | {:^6} | {:>3}/{:<2} | {}",
+            r.student,
+            if r.assembly_error.is_none() {"yes"} else {"no"},
+            r.faults.len(),
+            r.passed,
+            total,
+            notes,
+            width = width);
+    }
+}
+
+fn to_csv(reports : &[Report], total : usize) -> String {
+    let mut csv = String::from("student,assembly_error,runtime_faults,tests_passed,tests_total,notes\n");
+    for r in reports {
+        let notes = r.faults.iter().chain(&r.failures).cloned().collect::<Vec<_>>().join("; ");
+        csv.push_str(&format!("{},{},{},{},{},{}\n",
+            csv_field(&r.student),
+            csv_field(r.assembly_error.as_deref().unwrap_or("")),
+            r.faults.len(),
+            r.passed,
+            total,
+            csv_field(&notes)));
+    }
+    csv
+}
+
+fn csv_field(s : &str) -> String {
+    if s.contains([',','"','\n']) {
+        format!("\"{}\"",s.replace('"',"\"\""))
+    } else {
+        s.to_string()
+    }
+}
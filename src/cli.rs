@@ -0,0 +1,409 @@
+//! Command line parsing. Every subcommand has its own options and `--help`.
+use std::{
+    io::{self,Read},
+    ops::Range,
+    path::PathBuf,
+};
+use rasm::{Config,DisplayStyle,DEFAULT_MAX_STEPS};
+
+pub const USAGE : &str =
+r"
+USAGE:
+    rasm-cli <command> [options] <file>
+    rasm-cli <command> --help
+COMMANDS:
+    run      : run a program to the end
+    step     : step through a program one instruction at a time
+    trace    : run a program, printing a trace table of every instruction
+    check    : assemble a program and report any errors or likely mistakes
+    assemble : write the machine code of a program to a file
+    disasm   : show the instructions in a machine code file
+    fmt      : rewrite a program in the standard layout
+    test     : run a program, or a directory of them, against a test spec
+    lsp      : start a language server for editors
+    dap      : start a debug adapter for editors
+    repl     : type in instructions and run each one straight away
+Note:
+    <file> can be '-' to read the program from standard input
+";
+
+const RUN_USAGE : &str =
+r"
+USAGE:
+    rasm-cli run [options] <file>
+Runs the program to the end, IN and OUT use the terminal
+OPTIONS:
+    -h | --help : help
+    --max-steps <n> : give up after n steps, 0 for no limit (default 100000)
+    --no-loop-detection : keep going when the program comes back to a state it was in before
+";
+
+const STEP_USAGE : &str =
+r"
+USAGE:
+    rasm-cli step [options] <file>
+Steps through the program one instruction at a time, showing the CPU after each one
+OPTIONS:
+    -h | --help : help
+    -b | --binary : show acc and ix in binary
+    -x | --hex : show acc and ix in hexadecimal
+    --style <denary|binary|hex|all> : show values in the given style, all shows every one side by side
+    --max-steps <n> : give up after n steps, 0 for no limit (default 100000)
+    --no-loop-detection : keep going when the program comes back to a state it was in before
+    --explain : say in plain English what each instruction did and the values it used
+    --fde : step through each part of the fetch-decode-execute cycle, showing MAR, MDR and CIR
+    --mem <first>-<last> : the memory addresses to show (default the addresses the program uses)
+Note:
+    Vertical bar '|' means 'or'
+    The program can not be read from standard input as it is used for key presses
+";
+
+const TRACE_USAGE : &str =
+r"
+USAGE:
+    rasm-cli trace [options] <file>
+Runs the program to the end, printing a row of a trace table for every instruction
+OPTIONS:
+    -h | --help : help
+    -b | --binary : show acc and ix in binary
+    -x | --hex : show acc and ix in hexadecimal
+    --style <denary|binary|hex|all> : show values in the given style, all shows every one side by side
+    --max-steps <n> : give up after n steps, 0 for no limit (default 100000)
+    --no-loop-detection : keep going when the program comes back to a state it was in before
+Note:
+    Vertical bar '|' means 'or'
+";
+
+const CHECK_USAGE : &str =
+r"
+USAGE:
+    rasm-cli check [options] <file>
+Assembles the program and reports any errors without running it, then warns about
+likely mistakes: a missing END, code that can never run, labels that are never jumped to,
+variables read before anything is stored in them, CMP without a conditional jump after it
+and jumps to a numbered address rather than a label
+OPTIONS:
+    -h | --help : help
+    --dot <file> : also write the control flow graph as a Graphviz DOT file, '-' for standard output
+                   render it with e.g. 'dot -Tsvg program.dot -o program.svg'
+";
+
+const ASSEMBLE_USAGE : &str =
+r"
+USAGE:
+    rasm-cli assemble [options] <file>
+Writes the machine code of the program, by default next to it with the extension .rbin
+OPTIONS:
+    -h | --help : help
+    -o | --output <file> : where to write the machine code, '-' for standard output
+    --listing <file> : also write a listing of every source line with the address, machine code and
+                       instruction it became, then the memory each data declaration fills and every
+                       label, variable and constant, '-' for standard output
+Note:
+    Vertical bar '|' means 'or'
+";
+
+const DISASM_USAGE : &str =
+r"
+USAGE:
+    rasm-cli disasm [options] <file>
+Shows the instructions in a machine code file written by 'assemble'
+OPTIONS:
+    -h | --help : help
+";
+
+const FMT_USAGE : &str =
+r"
+USAGE:
+    rasm-cli fmt [options] <file>
+Rewrites the program in place with labels flush left, mnemonics indented and upper cased,
+and operands and comments lined up. A program from standard input is written to standard output
+OPTIONS:
+    -h | --help : help
+    --check : only report whether the program is already formatted
+";
+
+const TEST_USAGE : &str =
+r"
+USAGE:
+    rasm-cli test [options] --spec <spec> <file or directory>
+Runs the program against every test in the spec. Given a directory every .rasm file in it
+is graded and a summary is shown for each one
+OPTIONS:
+    -h | --help : help
+    --spec <file> : the test spec to run
+    --csv <file> : also write the summary of a directory as CSV
+    --max-steps <n> : give up on a test after n steps, 0 for no limit (default 100000)
+    --no-loop-detection : keep going when a program comes back to a state it was in before
+";
+
+const LSP_USAGE : &str =
+r"
+USAGE:
+    rasm-cli lsp [options]
+Starts a language server that talks to an editor over standard input and output, giving
+errors and warnings as you type, docs on hover, go to definition, find references and completion
+OPTIONS:
+    -h | --help : help
+    --stdio : accepted for editors that pass it, standard input and output are always used
+";
+
+const DAP_USAGE : &str =
+r"
+USAGE:
+    rasm-cli dap [options]
+Starts a debug adapter that talks to an editor over standard input and output, so a program
+can be stepped through with breakpoints while watching the registers and variables.
+The launch configuration gives the program to debug as 'program' and, as standard input is
+taken, the characters to give to IN as 'input'. Output from OUT goes to the debug console
+OPTIONS:
+    -h | --help : help
+";
+
+const REPL_USAGE : &str =
+r"
+USAGE:
+    rasm-cli repl [options] [file]
+Runs each instruction as soon as it is typed and shows the CPU after it.
+Given a file it is run first, and its labels and variables can then be used
+OPTIONS:
+    -h | --help : help
+    -b | --binary : show acc and ix in binary
+    -x | --hex : show acc and ix in hexadecimal
+    --style <denary|binary|hex|all> : show values in the given style, all shows every one side by side
+Note:
+    Vertical bar '|' means 'or'
+    Type :help once it has started for the commands it understands
+";
+
+/// Where a program is read from
+pub enum Source {
+    Stdin,
+    File(PathBuf),
+}
+impl Source {
+    pub fn name(&self) -> String {
+        match self {
+            Source::Stdin      => String::from("<stdin>"),
+            Source::File(path) => path.display().to_string(),
+        }
+    }
+    pub fn read(&self) -> io::Result<String> {
+        match self {
+            Source::Stdin => {
+                let mut src = String::new();
+                io::stdin().read_to_string(&mut src)?;
+                Ok(src)
+            },
+            Source::File(path) => {
+                Ok(String::from_utf8_lossy(&std::fs::read(path)?).into_owned())
+            },
+        }
+    }
+}
+
+pub struct RunArgs {
+    pub source : Source,
+    /// The display style, step limit and loop detection asked for
+    pub config : Config,
+    /// Describe each instruction as it is stepped over
+    pub explain : bool,
+    /// Step through the fetch-decode-execute cycle rather than whole instructions
+    pub fde : bool,
+    /// The memory addresses to show, by default those the program uses
+    pub memory : Option<Range<usize>>,
+}
+pub struct AssembleArgs {
+    pub source : Source,
+    pub output : Option<PathBuf>,
+    /// Where to write a listing of the program
+    pub listing : Option<PathBuf>,
+}
+pub struct CheckArgs {
+    pub source : Source,
+    pub dot : Option<PathBuf>,
+}
+pub struct FmtArgs {
+    pub source : Source,
+    pub check : bool,
+}
+pub struct ReplArgs {
+    pub file : Option<PathBuf>,
+    pub style : DisplayStyle,
+}
+pub struct TestArgs {
+    pub source : Source,
+    pub spec : PathBuf,
+    pub csv : Option<PathBuf>,
+    /// The step limit and loop detection each test is run with
+    pub config : Config,
+}
+pub enum Command {
+    Help(&'static str),
+    Run(RunArgs),
+    Step(RunArgs),
+    Trace(RunArgs),
+    Check(CheckArgs),
+    Assemble(AssembleArgs),
+    Disasm(PathBuf),
+    Fmt(FmtArgs),
+    Test(TestArgs),
+    Lsp,
+    Dap,
+    Repl(ReplArgs),
+}
+
+pub struct CliError {
+    pub message : String,
+    pub usage : &'static str,
+}
+
+pub fn parse(mut args : impl Iterator<Item = String>) -> Result<Command,CliError> {
+    args.next();
+    let command = args.next().ok_or(CliError {message : String::from("No command given"),usage : USAGE})?;
+    let usage = match command.as_str() {
+        "run"      => RUN_USAGE,
+        "step"     => STEP_USAGE,
+        "trace"    => TRACE_USAGE,
+        "check"    => CHECK_USAGE,
+        "assemble" => ASSEMBLE_USAGE,
+        "disasm"   => DISASM_USAGE,
+        "fmt"      => FMT_USAGE,
+        "test"     => TEST_USAGE,
+        "lsp"      => LSP_USAGE,
+        "dap"      => DAP_USAGE,
+        "repl"     => REPL_USAGE,
+        "help" | "-h" | "--help" => {
+            let usage = match args.next().as_deref() {
+                Some(command) => parse(vec![String::new(),command.to_string(),String::from("--help")].into_iter())?,
+                None          => Command::Help(USAGE),
+            };
+            return Ok(usage);
+        },
+        _ => return Err(CliError {message : format!("Unknown command '{}'",command),usage : USAGE}),
+    };
+    let err = |message : String| CliError {message,usage};
+
+    let mut file : Option<String> = None;
+    let mut style : Option<DisplayStyle> = None;
+    let mut max_steps = DEFAULT_MAX_STEPS;
+    let mut detect_loops = true;
+    let mut spec : Option<PathBuf> = None;
+    let mut csv : Option<PathBuf> = None;
+    let mut output : Option<PathBuf> = None;
+    let mut dot : Option<PathBuf> = None;
+    let mut listing : Option<PathBuf> = None;
+    let mut check = false;
+    let mut explain = false;
+    let mut fde = false;
+    let mut memory : Option<Range<usize>> = None;
+    let shows_cpu = matches!(command.as_str(),"step" | "trace" | "repl");
+    let runs = matches!(command.as_str(),"run" | "step" | "trace" | "test");
+    while let Some(arg) = args.next() {
+        let value = |args : &mut dyn Iterator<Item = String>| {
+            args.next().ok_or_else(|| err(format!("{} expects a value after it",arg)))
+        };
+        let new_style = match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(usage)),
+            "-b" | "--binary" if shows_cpu => Some(DisplayStyle::Binary),
+            "-x" | "--hex" if shows_cpu    => Some(DisplayStyle::Hex),
+            "--style" if shows_cpu => Some(match value(&mut args)?.as_str() {
+                "denary" => DisplayStyle::Denary,
+                "binary" => DisplayStyle::Binary,
+                "hex"    => DisplayStyle::Hex,
+                "all"    => DisplayStyle::All,
+                s        => return Err(err(format!("Unknown display style '{}'",s))),
+            }),
+            "--max-steps" if runs => {
+                let n = value(&mut args)?;
+                max_steps = n.parse().map_err(|_| err(format!("--max-steps expects a number, not '{}'",n)))?;
+                None
+            },
+            "--no-loop-detection" if runs => {detect_loops = false; None},
+            "--spec" if command == "test" => {spec = Some(value(&mut args)?.into()); None},
+            "--csv" if command == "test"  => {csv = Some(value(&mut args)?.into()); None},
+            "-o" | "--output" if command == "assemble" => {output = Some(value(&mut args)?.into()); None},
+            "--listing" if command == "assemble" => {listing = Some(value(&mut args)?.into()); None},
+            "--check" if command == "fmt" => {check = true; None},
+            "--explain" if command == "step" => {explain = true; None},
+            "--fde" if command == "step" => {fde = true; None},
+            "--mem" if command == "step" => {
+                let range = value(&mut args)?;
+                let bounds = range.split_once('-').and_then(|(first,last)| Some((first.parse::<usize>().ok()?,last.parse::<usize>().ok()?)));
+                memory = match bounds {
+                    Some((first,last)) if first <= last => Some(first..last + 1),
+                    _ => return Err(err(format!("--mem expects a range of addresses like 0-15, not '{}'",range))),
+                };
+                None
+            },
+            "--dot" if command == "check" => {dot = Some(value(&mut args)?.into()); None},
+            "--stdio" if command == "lsp" => None,
+            a if a.starts_with('-') && a != "-" => {
+                return Err(err(format!("Unknown option '{}' for {}",a,command)));
+            },
+            _ => {
+                if let Some(first) = &file {
+                    return Err(err(format!("Only one file can be given, found '{}' and '{}'",first,arg)));
+                }
+                file = Some(arg);
+                None
+            },
+        };
+        if let Some(new_style) = new_style {
+            if style.is_some() {
+                return Err(err(String::from("Only one of --binary, --hex or --style can be given")));
+            }
+            style = Some(new_style);
+        }
+    }
+
+    if command == "lsp" || command == "dap" {
+        if let Some(file) = file {
+            return Err(err(format!("{} does not take a file, found '{}'",command,file)));
+        }
+        return Ok(if command == "lsp" {Command::Lsp} else {Command::Dap});
+    }
+    if command == "repl" {
+        let style = style.unwrap_or(DisplayStyle::Denary);
+        return match file.as_deref() {
+            Some("-") => Err(err(String::from("repl needs the program in a file, standard input is used for typing"))),
+            Some(f) if !PathBuf::from(f).exists() => Err(err(format!("'{}' could not be found",f))),
+            _ => Ok(Command::Repl(ReplArgs {file : file.map(PathBuf::from),style})),
+        };
+    }
+    let file = file.ok_or_else(|| err(String::from("No file given")))?;
+    let source = if file == "-" {
+        Source::Stdin
+    } else {
+        let path = PathBuf::from(&file);
+        if !path.exists() {
+            return Err(err(format!("'{}' could not be found",file)));
+        }
+        Source::File(path)
+    };
+    if explain && fde {
+        return Err(err(String::from("Only one of --explain or --fde can be given")));
+    }
+    let config = Config {style : style.unwrap_or(DisplayStyle::Denary), max_steps, detect_loops};
+    Ok(match command.as_str() {
+        "run"   => Command::Run(RunArgs {source,config,explain,fde,memory}),
+        "step"  => {
+            if let Source::Stdin = source {
+                return Err(err(String::from("step needs the program in a file, standard input is used for key presses")));
+            }
+            Command::Step(RunArgs {source,config,explain,fde,memory})
+        },
+        "trace" => Command::Trace(RunArgs {source,config,explain,fde,memory}),
+        "check" => Command::Check(CheckArgs {source,dot}),
+        "assemble" => Command::Assemble(AssembleArgs {source,output,listing}),
+        "disasm" => match source {
+            Source::File(path) => Command::Disasm(path),
+            Source::Stdin      => return Err(err(String::from("disasm needs a machine code file"))),
+        },
+        "fmt" => Command::Fmt(FmtArgs {source,check}),
+        _ => {
+            let spec = spec.ok_or_else(|| err(String::from("test needs a spec given with --spec")))?;
+            Command::Test(TestArgs {source,spec,csv,config})
+        },
+    })
+}
@@ -0,0 +1,213 @@
+//! The subcommands that don't need a module of their own.
+use std::{fs,io::{self,Write}};
+use rasm::{assemble,assemble_from,VarFormat,lint,Machine,Program,FLAGS};
+use rasm::tooling::{format,listing,object,to_dot};
+use crate::cli::{AssembleArgs,CheckArgs,FmtArgs,RunArgs,Source};
+
+/// Reads and assembles the program, printing every problem found if it does not assemble
+pub fn load(source : &Source) -> Option<Program> {
+    let src = match source.read() {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("{}: {}",source.name(),e);
+            return None
+        }
+    };
+    let program = match source {
+        Source::File(path) => assemble_from(&src,path),
+        Source::Stdin      => assemble(&src),
+    };
+    match program {
+        Ok(program) => Some(program),
+        Err(diagnostics) => {
+            for d in diagnostics.iter() {
+                eprintln!("{}: {}",source.name(),d);
+            }
+            None
+        }
+    }
+}
+
+pub fn run(args : RunArgs) -> bool {
+    let program = match load(&args.source) {
+        Some(program) => program,
+        None => return false,
+    };
+    let mut machine = Machine::new(program,args.config);
+    match machine.run() {
+        Ok(_) => true,
+        Err(fault) => {
+            eprintln!("\nProgram stopped on {}: {}",machine.location(),fault);
+            false
+        }
+    }
+}
+
+pub fn trace(args : RunArgs) -> bool {
+    let program = match load(&args.source) {
+        Some(program) => program,
+        None => return false,
+    };
+    let mut machine = Machine::with_io(program,args.config,io::stdin(),Vec::new());
+    let width = args.config.style.format(0).len().max(6);
+    //a column for every variable, each in its own format
+    let mut vars : Vec<(String,usize)> = machine.program().symbols().vars().map(|(name,addr)| (name.to_string(),addr)).collect();
+    vars.sort_by_key(|(_,addr)| *addr);
+    let var_width = |name : &str| {
+        let format = machine.program().symbols().format(name).unwrap_or(VarFormat::Style(args.config.style));
+        name.len().max(format.width())
+    };
+    let var_widths : Vec<usize> = vars.iter().map(|(name,_)| var_width(name)).collect();
+    let mut header = format!("{:>5} | {:>4} | {:<16} | {:>w$} | {:>w$} | {:>3} | N V Z C |",
+        "STEP","LINE","INSTRUCTION","ACC","IX","PC",w = width);
+    for ((name,_),w) in vars.iter().zip(&var_widths) {
+        header.push_str(&format!(" {:>w$} |",name,w = w));
+    }
+    println!("{} OUT",header);
+    while !machine.done() {
+        let (line,text) = match machine.current() {
+            Some((_,info)) => (info.line,info.text.clone()),
+            None => (0,String::new()),
+        };
+        let printed = machine.output().len();
+        if let Err(fault) = machine.step() {
+            eprintln!("Program stopped on {}: {}",machine.location(),fault);
+            return false
+        }
+        //OUT writes a whole character, so the bytes from one step are always valid UTF-8
+        let out = String::from_utf8_lossy(&machine.output()[printed..]).into_owned();
+        let mut row = format!("{:>5} | {:>4} | {:<16} | {:>w$} | {:>w$} | {:>3} | {} {} {} {} |",
+            machine.steps(),
+            line + 1,
+            text,
+            args.config.style.format(machine.acc()),
+            args.config.style.format(machine.ix()),
+            machine.pc(),
+            machine.flag(FLAGS::N) as u8,
+            machine.flag(FLAGS::V) as u8,
+            machine.flag(FLAGS::Z) as u8,
+            machine.flag(FLAGS::C) as u8,
+            w = width);
+        for ((name,addr),w) in vars.iter().zip(&var_widths) {
+            let value = machine.memory().get(*addr).unwrap_or(0);
+            row.push_str(&format!(" {:>w$} |",machine.program().symbols().format_value(name,value,args.config.style),w = w));
+        }
+        println!("{} {}",row,out.escape_debug());
+    }
+    true
+}
+
+pub fn check(args : CheckArgs) -> bool {
+    let source = &args.source;
+    let program = match load(source) {
+        Some(program) => program,
+        None => return false,
+    };
+    let warnings = lint(&program);
+    for w in &warnings {
+        println!("{}: {}",source.name(),w);
+    }
+    let instructions = (0..program.code.len()).filter(|&pc| !program.is_gap(pc)).count();
+    println!("{}: {} instructions, {} warnings",source.name(),instructions,warnings.len());
+    if let Some(dot) = args.dot {
+        let graph = to_dot(&program);
+        let written = if dot.as_os_str() == "-" {
+            io::stdout().write_all(graph.as_bytes())
+        } else {
+            fs::write(&dot,graph)
+        };
+        if let Err(e) = written {
+            eprintln!("{}: {}",dot.display(),e);
+            return false
+        }
+    }
+    true
+}
+
+pub fn assemble_to_file(args : AssembleArgs) -> bool {
+    let program = match load(&args.source) {
+        Some(program) => program,
+        None => return false,
+    };
+    let bytes = object::write(&program);
+    let output = match (args.output,&args.source) {
+        (Some(output),_) => output,
+        (None,Source::File(path)) => path.with_extension("rbin"),
+        (None,Source::Stdin) => "-".into(),
+    };
+    let written = if output.as_os_str() == "-" {
+        io::stdout().write_all(&bytes)
+    } else {
+        fs::write(&output,&bytes)
+    };
+    if let Err(e) = written {
+        eprintln!("{}: {}",output.display(),e);
+        return false
+    }
+    if let Some(file) = args.listing {
+        let text = listing(&program);
+        let written = if file.as_os_str() == "-" {
+            io::stdout().write_all(text.as_bytes())
+        } else {
+            fs::write(&file,text)
+        };
+        if let Err(e) = written {
+            eprintln!("{}: {}",file.display(),e);
+            return false
+        }
+    }
+    true
+}
+
+pub fn disasm(file : &std::path::Path) -> bool {
+    let object = fs::read(file)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| object::read(&bytes));
+    match object {
+        Ok(object) => {
+            for (addr,instr) in object.code.iter().enumerate() {
+                let words = object::encode(instr);
+                println!("{:>4}  {:04X} {:04X}  {}",addr,words[0],words[1],instr);
+            }
+            if !object.data.is_empty() {
+                println!("\ndata");
+                for (addr,value) in object.data {
+                    println!("{:>4}  {:04X}       #{}",addr,value as u16,value);
+                }
+            }
+            true
+        },
+        Err(e) => {
+            eprintln!("{}: {}",file.display(),e);
+            false
+        }
+    }
+}
+
+pub fn fmt(args : FmtArgs) -> bool {
+    let src = match args.source.read() {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("{}: {}",args.source.name(),e);
+            return false
+        }
+    };
+    let formatted = format(&src);
+    if args.check {
+        if formatted == src {
+            return true
+        }
+        println!("{} is not formatted, run 'rasm-cli fmt' on it",args.source.name());
+        return false
+    }
+    let written = match &args.source {
+        Source::Stdin      => io::stdout().write_all(formatted.as_bytes()),
+        Source::File(_) if formatted == src => Ok(()),
+        Source::File(path) => fs::write(path,&formatted),
+    };
+    if let Err(e) = written {
+        eprintln!("{}: {}",args.source.name(),e);
+        return false
+    }
+    true
+}
@@ -0,0 +1,429 @@
+//! A debug adapter, so that editors can step through a program with breakpoints.
+//!
+//! It speaks the Debug Adapter Protocol over standard input and output. As standard input is
+//! taken, the characters for `IN` are given by `input` in the launch configuration, one per `IN`,
+//! and whatever the program outputs is shown in the debug console.
+use std::{
+    collections::HashSet,
+    io::{self,Cursor},
+    path::PathBuf,
+    sync::mpsc::{self,TryRecvError},
+    thread,
+};
+use rasm::{build_code,Config,DisplayStyle,Machine,FLAGS};
+use crate::json::{self,Json};
+
+/// Instructions run between checking for a pause while continuing
+const BATCH : usize = 1000;
+/// There is only ever one thread
+const THREAD : usize = 1;
+const REGISTERS : usize = 1;
+const VARIABLES : usize = 2;
+
+type Debuggee = Machine<Cursor<Vec<u8>>,Vec<u8>>;
+
+struct Session {
+    seq : usize,
+    path : Option<PathBuf>,
+    machine : Option<Debuggee>,
+    /// Lines breakpoints were asked for, counted from 0
+    requested : Vec<usize>,
+    /// Addresses of the instructions that execution stops at
+    breakpoints : HashSet<usize>,
+    stop_on_entry : bool,
+    configured : bool,
+    started : bool,
+    running : bool,
+    faulted : bool,
+    /// Bytes of output already sent to the console
+    printed : usize,
+}
+
+/// Serves requests until the client disconnects, returning whether it did so cleanly
+pub fn run() -> bool {
+    //requests are read on their own thread so a running program can be paused
+    let (sender,receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        loop {
+            match json::read_message(&mut input) {
+                Ok(Some(Ok(message))) => if sender.send(message).is_err() {
+                    break
+                },
+                Ok(Some(Err(e))) => eprintln!("dap: {}",e),
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("dap: {}",e);
+                    break
+                },
+            }
+        }
+    });
+    let mut session = Session {
+        seq : 0,
+        path : None,
+        machine : None,
+        requested : Vec::new(),
+        breakpoints : HashSet::new(),
+        stop_on_entry : false,
+        configured : false,
+        started : false,
+        running : false,
+        faulted : false,
+        printed : 0,
+    };
+    loop {
+        let request = if session.running {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return false,
+            }
+        };
+        if let Some(request) = request {
+            if !session.handle(&request) {
+                return true;
+            }
+        }
+        if session.running {
+            session.run_batch();
+        }
+    }
+}
+
+impl Session {
+    /// Handles one request, returning false once the client disconnects
+    fn handle(&mut self, request : &Json) -> bool {
+        let args = request.get("arguments");
+        let result = match request.get("command").as_str().unwrap_or("") {
+            "initialize" => {
+                let capabilities = Json::object(vec![
+                    ("supportsConfigurationDoneRequest",true.into()),
+                    ("supportsTerminateRequest",true.into()),
+                    ("supportsEvaluateForHovers",true.into()),
+                ]);
+                self.respond(request,Ok(capabilities));
+                self.event("initialized",Json::Null);
+                return true;
+            },
+            "launch" => {
+                //the program only starts once the client has been told it launched
+                let result = self.launch(args);
+                self.respond(request,result);
+                self.start();
+                return true;
+            },
+            "setBreakpoints" => {
+                self.requested = args.get("breakpoints").as_array().iter()
+                    .filter_map(|b| b.get("line").as_u64())
+                    .map(|line| (line as usize).saturating_sub(1))
+                    .collect();
+                Ok(Json::object(vec![("breakpoints",self.resolve_breakpoints().into())]))
+            },
+            "configurationDone" => {
+                self.configured = true;
+                self.respond(request,Ok(Json::Null));
+                self.start();
+                return true;
+            },
+            "threads" => Ok(Json::object(vec![
+                ("threads",Json::Array(vec![Json::object(vec![("id",THREAD.into()),("name","main".into())])])),
+            ])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(Json::object(vec![("scopes",Json::Array(vec![
+                Json::object(vec![("name","Registers".into()),("variablesReference",REGISTERS.into()),("expensive",false.into())]),
+                Json::object(vec![("name","Variables".into()),("variablesReference",VARIABLES.into()),("expensive",false.into())]),
+            ]))])),
+            "variables" => {
+                let style = match args.get("format").get("hex").as_bool() {
+                    Some(true) => DisplayStyle::Hex,
+                    _          => DisplayStyle::Denary,
+                };
+                let reference = args.get("variablesReference").as_u64().unwrap_or(0) as usize;
+                Ok(Json::object(vec![("variables",Json::Array(self.variables(reference,style)))]))
+            },
+            "evaluate" => {
+                let expression = args.get("expression").as_str().unwrap_or("").trim();
+                match self.value_of(expression) {
+                    Some(value) => Ok(Json::object(vec![("result",value.to_string().into()),("variablesReference",0usize.into())])),
+                    None => Err(format!("'{}' is not a register or variable",expression)),
+                }
+            },
+            "next" | "stepIn" | "stepOut" => {
+                self.respond(request,Ok(Json::Null));
+                if self.step() {
+                    self.stopped("step",None);
+                }
+                return true;
+            },
+            "continue" => {
+                self.running = self.machine.is_some();
+                Ok(Json::object(vec![("allThreadsContinued",true.into())]))
+            },
+            "pause" => {
+                self.respond(request,Ok(Json::Null));
+                if self.running {
+                    self.running = false;
+                    self.stopped("pause",None);
+                }
+                return true;
+            },
+            "terminate" => {
+                self.respond(request,Ok(Json::Null));
+                self.running = false;
+                self.event("terminated",Json::Null);
+                return true;
+            },
+            "disconnect" => {
+                self.respond(request,Ok(Json::Null));
+                return false;
+            },
+            command => Err(format!("'{}' is not supported",command)),
+        };
+        self.respond(request,result);
+        true
+    }
+
+    fn launch(&mut self, args : &Json) -> Result<Json,String> {
+        let path = PathBuf::from(args.get("program").as_str().ok_or("launch needs the program to debug")?);
+        let code = build_code(&path).map_err(|diagnostics| {
+            for d in diagnostics.iter() {
+                self.output("stderr",&format!("{}: {}\n",path.display(),d));
+            }
+            format!("{} does not assemble",path.display())
+        })?;
+        let input : String = args.get("input").as_str().unwrap_or("").chars().flat_map(|c| [c,'\n']).collect();
+        let config = Config {style : DisplayStyle::Denary, max_steps : 0, detect_loops : true};
+        self.machine = Some(Machine::with_io(code,config,Cursor::new(input.into_bytes()),Vec::new()));
+        self.path = Some(path);
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        if !self.requested.is_empty() {
+            let breakpoints = self.resolve_breakpoints();
+            for breakpoint in breakpoints {
+                self.event("breakpoint",Json::object(vec![("reason","changed".into()),("breakpoint",breakpoint)]));
+            }
+        }
+        Ok(Json::Null)
+    }
+
+    /// Moves every requested breakpoint to the first instruction at or after its line. A macro
+    /// call stops once, at the first instruction it expands to
+    fn resolve_breakpoints(&mut self) -> Vec<Json> {
+        self.breakpoints.clear();
+        let mut resolved = Vec::new();
+        for (id,&line) in self.requested.iter().enumerate() {
+            let (pc,actual) = match &self.machine {
+                //breakpoints can only be set in the program itself, not the files it includes
+                Some(machine) => {
+                    let program = machine.program();
+                    let found = program.debug_info.iter().enumerate()
+                        .filter(|&(pc,info)| info.file.is_none() && program.starts_line(pc))
+                        .find(|&(_,info)| info.line >= line);
+                    (found.map(|(pc,_)| pc),found.map(|(_,info)| info.line))
+                },
+                None => (None,Some(line)),
+            };
+            let mut breakpoint = vec![("id",Json::from(id + 1)),("verified",actual.is_some().into())];
+            self.breakpoints.extend(pc);
+            if let Some(actual) = actual {
+                breakpoint.push(("line",(actual + 1).into()));
+            }
+            resolved.push(Json::object(breakpoint));
+        }
+        resolved
+    }
+
+    /// Starts the program once it is both launched and configured
+    fn start(&mut self) {
+        if self.started || !self.configured || self.machine.is_none() {
+            return;
+        }
+        self.started = true;
+        if self.stop_on_entry {
+            self.stopped("entry",None);
+        } else if self.at_breakpoint() {
+            self.stopped("breakpoint",None);
+        } else {
+            self.running = true;
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.machine.as_ref().is_some_and(|m| self.breakpoints.contains(&(m.pc() as usize)))
+    }
+
+    fn run_batch(&mut self) {
+        for _ in 0..BATCH {
+            if !self.step() {
+                return;
+            }
+            if self.at_breakpoint() {
+                self.running = false;
+                self.stopped("breakpoint",None);
+                return;
+            }
+        }
+    }
+
+    /// Executes one instruction, returning whether the program can go on
+    fn step(&mut self) -> bool {
+        let machine = match &mut self.machine {
+            Some(machine) if !self.faulted => machine,
+            _ => {
+                self.finish(1);
+                return false;
+            },
+        };
+        let result = machine.step();
+        let location = machine.location();
+        let done = machine.done();
+        let output = String::from_utf8_lossy(&machine.output()[self.printed..]).into_owned();
+        self.printed += output.len();
+        if !output.is_empty() {
+            self.output("stdout",&output);
+        }
+        match result {
+            Err(fault) => {
+                self.running = false;
+                self.faulted = true;
+                self.output("stderr",&format!("\nProgram stopped on {}: {}\n",location,fault));
+                self.stopped("exception",Some(fault.to_string()));
+                false
+            },
+            Ok(()) if done => {
+                self.finish(0);
+                false
+            },
+            Ok(()) => true,
+        }
+    }
+
+    fn finish(&mut self, exit_code : usize) {
+        self.running = false;
+        self.event("exited",Json::object(vec![("exitCode",exit_code.into())]));
+        self.event("terminated",Json::Null);
+    }
+
+    fn stack_trace(&self) -> Json {
+        let frames = match (&self.machine,&self.path) {
+            (Some(machine),Some(path)) => match machine.current() {
+                Some((_,info)) => {
+                    let path = info.file.as_ref().unwrap_or(path);
+                    let name = path.file_name().map_or(String::new(),|n| n.to_string_lossy().into_owned());
+                    let source = Json::object(vec![("name",name.into()),("path",path.display().to_string().into())]);
+                    vec![Json::object(vec![
+                        ("id",0usize.into()),
+                        ("name",info.text.clone().into()),
+                        ("source",source),
+                        ("line",(info.line + 1).into()),
+                        ("column",1usize.into()),
+                    ])]
+                },
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        Json::object(vec![("totalFrames",frames.len().into()),("stackFrames",Json::Array(frames))])
+    }
+
+    fn variables(&self, reference : usize, style : DisplayStyle) -> Vec<Json> {
+        let machine = match &self.machine {
+            Some(machine) => machine,
+            None => return Vec::new(),
+        };
+        let values : Vec<(String,i16)> = match reference {
+            REGISTERS => {
+                let mut registers = vec![
+                    (String::from("ACC"),machine.acc()),
+                    (String::from("IX"),machine.ix()),
+                    (String::from("PC"),machine.pc() as i16),
+                ];
+                for (name,flag) in [("N",FLAGS::N),("V",FLAGS::V),("Z",FLAGS::Z),("C",FLAGS::C)] {
+                    registers.push((name.to_string(),machine.flag(flag) as i16));
+                }
+                registers
+            },
+            VARIABLES => {
+                let mut vars : Vec<(&str,usize)> = machine.program().symbols().vars().collect();
+                vars.sort_by_key(|&(_,addr)| addr);
+                vars.into_iter()
+                    .map(|(name,addr)| (name.to_string(),machine.memory().get(addr).unwrap_or(0)))
+                    .collect()
+            },
+            _ => Vec::new(),
+        };
+        values.into_iter()
+            .map(|(name,value)| Json::object(vec![
+                ("name",name.into()),
+                ("value",style.format(value).into()),
+                ("variablesReference",0usize.into()),
+            ]))
+            .collect()
+    }
+
+    fn value_of(&self, name : &str) -> Option<i16> {
+        let machine = self.machine.as_ref()?;
+        match name {
+            "ACC" => Some(machine.acc()),
+            "IX"  => Some(machine.ix()),
+            "PC"  => Some(machine.pc() as i16),
+            _ => {
+                let (_,addr) = machine.program().symbols().vars().find(|&(n,_)| n == name)?;
+                machine.memory().get(addr)
+            },
+        }
+    }
+
+    fn stopped(&mut self, reason : &str, text : Option<String>) {
+        let mut body = vec![
+            ("reason",reason.into()),
+            ("threadId",THREAD.into()),
+            ("allThreadsStopped",true.into()),
+        ];
+        if let Some(text) = text {
+            body.push(("text",text.into()));
+        }
+        self.event("stopped",Json::object(body));
+    }
+
+    fn output(&mut self, category : &str, text : &str) {
+        self.event("output",Json::object(vec![("category",category.into()),("output",text.into())]));
+    }
+
+    fn event(&mut self, event : &str, body : Json) {
+        let mut message = vec![("type","event".into()),("event",event.into())];
+        if body != Json::Null {
+            message.push(("body",body));
+        }
+        self.send(message);
+    }
+
+    fn respond(&mut self, request : &Json, result : Result<Json,String>) {
+        let mut message = vec![
+            ("type","response".into()),
+            ("request_seq",request.get("seq").clone()),
+            ("command",request.get("command").clone()),
+            ("success",result.is_ok().into()),
+        ];
+        match result {
+            Ok(Json::Null) => (),
+            Ok(body) => message.push(("body",body)),
+            Err(e) => message.push(("message",e.into())),
+        }
+        self.send(message);
+    }
+
+    fn send(&mut self, mut message : Vec<(&str,Json)>) {
+        self.seq += 1;
+        message.insert(0,("seq",self.seq.into()));
+        if let Err(e) = json::write_message(&mut io::stdout(),&Json::object(message)) {
+            eprintln!("dap: {}",e);
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Just enough JSON for the language server and debug adapter protocols.
+use std::fmt::{self,Display,Formatter,Write};
+
+/// The longest message body read, far more than any request needs. A longer one is skipped
+/// without being kept in memory
+const MAX_LENGTH : usize = 1 << 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String,Json)>),
+}
+
+impl Json {
+    /// Builds an object from `(key, value)` pairs
+    pub fn object<'a>(fields : impl IntoIterator<Item = (&'a str,Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k,v)| (k.to_string(),v)).collect())
+    }
+    pub fn get(&self, key : &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k,_)| k == key).map_or(&Json::Null,|(_,v)| v),
+            _ => &Json::Null,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::Number(n) if n >= 0.0 => Some(n as u64),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+    pub fn parse(src : &str) -> Result<Json,String> {
+        let mut parser = Parser {src : src.as_bytes(), pos : 0};
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != src.len() {
+            return Err(format!("unexpected data at {}",parser.pos));
+        }
+        Ok(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s : &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+impl From<String> for Json {
+    fn from(s : String) -> Self {
+        Json::String(s)
+    }
+}
+impl From<bool> for Json {
+    fn from(b : bool) -> Self {
+        Json::Bool(b)
+    }
+}
+impl From<usize> for Json {
+    fn from(n : usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+impl From<i64> for Json {
+    fn from(n : i64) -> Self {
+        Json::Number(n as f64)
+    }
+}
+impl<T : Into<Json>> From<Vec<T>> for Json {
+    fn from(items : Vec<T>) -> Self {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f : &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null      => f.write_str("null"),
+            Json::Bool(b)   => write!(f,"{}",b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f,"{}",*n as i64),
+            Json::Number(n) => write!(f,"{}",n),
+            Json::String(s) => write_string(f,s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i,item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f,"{}",item)?;
+                }
+                f.write_char(']')
+            },
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i,(k,v)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f,k)?;
+                    write!(f,":{}",v)?;
+                }
+                f.write_char('}')
+            },
+        }
+    }
+}
+
+fn write_string(f : &mut Formatter<'_>, s : &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"'  => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f,"\\u{:04x}",c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+struct Parser<'a> {
+    src : &'a [u8],
+    pos : usize,
+}
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.src.get(self.pos),Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r')) {
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, word : &str, value : Json) -> Result<Json,String> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(format!("unexpected character at {}",self.pos))
+        }
+    }
+    fn value(&mut self) -> Result<Json,String> {
+        self.skip_whitespace();
+        match self.src.get(self.pos) {
+            Some(b'n') => self.expect("null",Json::Null),
+            Some(b't') => self.expect("true",Json::Bool(true)),
+            Some(b'f') => self.expect("false",Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.src.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.src.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {self.pos += 1; return Ok(Json::Array(items))},
+                        _ => return Err(format!("expected ',' or ']' at {}",self.pos)),
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.src.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.src.get(self.pos) != Some(&b':') {
+                        return Err(format!("expected ':' at {}",self.pos));
+                    }
+                    self.pos += 1;
+                    fields.push((key,self.value()?));
+                    self.skip_whitespace();
+                    match self.src.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {self.pos += 1; return Ok(Json::Object(fields))},
+                        _ => return Err(format!("expected ',' or '}}' at {}",self.pos)),
+                    }
+                }
+            },
+            Some(_) => {
+                let start = self.pos;
+                while matches!(self.src.get(self.pos),Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.src[start..self.pos]).unwrap()
+                    .parse().map(Json::Number)
+                    .map_err(|_| format!("invalid number at {}",start))
+            },
+            None => Err(String::from("unexpected end of input")),
+        }
+    }
+    fn string(&mut self) -> Result<String,String> {
+        if self.src.get(self.pos) != Some(&b'"') {
+            return Err(format!("expected a string at {}",self.pos));
+        }
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.src.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(bytes).map_err(|e| e.to_string());
+                },
+                Some(b'\\') => {
+                    let escaped = match self.src.get(self.pos + 1) {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => {
+                            let mut code = self.hex(self.pos + 2)?;
+                            self.pos += 4;
+                            //characters outside the BMP are a pair of UTF-16 surrogates, each escaped
+                            if (0xD800..0xDC00).contains(&code) && self.src.get(self.pos + 2..self.pos + 4) == Some(b"\\u") {
+                                let low = self.hex(self.pos + 4)?;
+                                if (0xDC00..0xE000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                    self.pos += 6;
+                                }
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        },
+                        Some(&c) => c as char,
+                        None => return Err(String::from("unexpected end of input")),
+                    };
+                    let mut buf = [0;4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                    self.pos += 2;
+                },
+                Some(&b) => {
+                    bytes.push(b);
+                    self.pos += 1;
+                },
+                None => return Err(String::from("unexpected end of input")),
+            }
+        }
+    }
+    /// The four hex digits of a `\u` escape starting at `at`
+    fn hex(&self, at : usize) -> Result<u32,String> {
+        let hex = self.src.get(at..at + 4).ok_or("truncated escape")?;
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return Err(format!("invalid escape at {}",at));
+        }
+        Ok(u32::from_str_radix(std::str::from_utf8(hex).unwrap(),16).unwrap())
+    }
+}
+
+/// Reads one `Content-Length` framed message, as used by both LSP and DAP. None at the end of
+/// the input, and an error message for a frame that isn't a message, which has been skipped so
+/// that the next one can be read
+pub fn read_message(input : &mut impl std::io::BufRead) -> std::io::Result<Option<Result<Json,String>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        //found anywhere in the line, so the next message isn't lost after a frame with no length
+        if let Some(n) = header.find("Content-Length:") {
+            length = header[n + "Content-Length:".len()..].trim().parse::<usize>().ok();
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None => return Ok(Some(Err(String::from("skipped a message without a valid Content-Length")))),
+    };
+    if length > MAX_LENGTH {
+        std::io::copy(&mut std::io::Read::take(&mut *input,length as u64),&mut std::io::sink())?;
+        return Ok(Some(Err(format!("skipped a message of {} bytes, the most that is read is {}",length,MAX_LENGTH))));
+    }
+    let mut body = vec![0;length];
+    input.read_exact(&mut body)?;
+    Ok(Some(Json::parse(&String::from_utf8_lossy(&body))
+        .map_err(|e| format!("skipped a message that is not valid JSON, {}",e))))
+}
+
+pub fn write_message(output : &mut impl std::io::Write, message : &Json) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(output,"Content-Length: {}\r\n\r\n{}",body.len(),body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(Json::parse("null"),Ok(Json::Null));
+        assert_eq!(Json::parse(" true "),Ok(Json::Bool(true)));
+        assert_eq!(Json::parse("false"),Ok(Json::Bool(false)));
+        assert_eq!(Json::parse("-1.5e3"),Ok(Json::Number(-1500.0)));
+        assert_eq!(Json::parse("0"),Ok(Json::Number(0.0)));
+        assert_eq!(Json::parse("\"hi\""),Ok(Json::from("hi")));
+        assert_eq!(Json::parse("[]"),Ok(Json::Array(Vec::new())));
+        assert_eq!(Json::parse("{}"),Ok(Json::Object(Vec::new())));
+    }
+
+    #[test]
+    fn parses_nesting() {
+        let json = Json::parse(r#"{"id": 3, "params": {"lines": [1, [2, 3], {"x": null}]}}"#).unwrap();
+        assert_eq!(json.get("id").as_u64(),Some(3));
+        let lines = json.get("params").get("lines").as_array();
+        assert_eq!(lines.len(),3);
+        assert_eq!(lines[1],Json::from(vec![2usize,3]));
+        assert_eq!(*lines[2].get("x"),Json::Null);
+        assert_eq!(*json.get("missing").get("deeper"),Json::Null);
+    }
+
+    #[test]
+    fn parses_escapes() {
+        assert_eq!(Json::parse(r#""a\"b\\c\/d\n\t\r""#),Ok(Json::from("a\"b\\c/d\n\t\r")));
+        assert_eq!(Json::parse(r#""\u0041\u00e9\u20AC""#),Ok(Json::from("Aé€")));
+        assert_eq!(Json::parse("\"é\""),Ok(Json::from("é")));
+    }
+
+    #[test]
+    fn parses_surrogate_pairs() {
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#),Ok(Json::from("😀")));
+        //one half of a pair on its own isn't a character
+        assert_eq!(Json::parse(r#""\ud83d""#),Ok(Json::from("\u{fffd}")));
+        assert_eq!(Json::parse(r#""\ud83dx""#),Ok(Json::from("\u{fffd}x")));
+        assert_eq!(Json::parse(r#""\ude00""#),Ok(Json::from("\u{fffd}")));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for src in ["","[1,","[1 2]","{\"a\" 1}","{\"a\":1,}","\"open","nul","1 2","-","\"\\u12\"","\"\\u+123\"","{1:2}"] {
+            assert!(Json::parse(src).is_err(),"{:?} parsed",src);
+        }
+    }
+
+    #[test]
+    fn writes_values() {
+        let json = Json::object(vec![
+            ("a",Json::from(vec![1usize,2])),
+            ("b","x\"y\\z\n\u{1}".into()),
+            ("c",Json::Number(0.5)),
+            ("d",Json::from(-3i64)),
+            ("e",Json::Null),
+        ]);
+        let text = json.to_string();
+        assert_eq!(text,r#"{"a":[1,2],"b":"x\"y\\z\n\u0001","c":0.5,"d":-3,"e":null}"#);
+        assert_eq!(Json::parse(&text),Ok(json));
+    }
+
+    /// Every message read from `input`, until its end or an IO error
+    fn read_all(input : &str) -> Vec<Result<Json,String>> {
+        let mut input = Cursor::new(input.as_bytes());
+        let mut messages = Vec::new();
+        while let Ok(Some(message)) = read_message(&mut input) {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut output = Vec::new();
+        write_message(&mut output,&Json::from("é")).unwrap();
+        write_message(&mut output,&Json::from(vec![1usize])).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Content-Length: 4\r\n\r\n\"é\""));
+        assert_eq!(read_all(&output),[Ok(Json::from("é")),Ok(Json::from(vec![1usize]))]);
+    }
+
+    #[test]
+    fn bad_frames_are_skipped() {
+        let messages = read_all("Content-Type: json\r\n\r\nContent-Length: 3\r\n\r\n[1,Content-Length: 4\r\n\r\nnull");
+        assert_eq!(messages.len(),3);
+        assert!(messages[0].is_err());
+        assert!(messages[1].is_err());
+        assert_eq!(messages[2],Ok(Json::Null));
+    }
+
+    #[test]
+    fn long_frames_are_skipped() {
+        let body = " ".repeat(MAX_LENGTH + 1);
+        let input = format!("Content-Length: {}\r\n\r\n{}Content-Length: 4\r\n\r\ntrue",body.len(),body);
+        let messages = read_all(&input);
+        assert_eq!(messages.len(),2);
+        assert!(messages[0].as_ref().is_err_and(|e| e.contains("the most that is read")));
+        assert_eq!(messages[1],Ok(Json::Bool(true)));
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let mut input = Cursor::new(&b"Content-Length: 10\r\n\r\nnull"[..]);
+        assert!(read_message(&mut input).is_err());
+        let mut input = Cursor::new(&b""[..]);
+        assert_eq!(read_message(&mut input).unwrap(),None);
+    }
+}
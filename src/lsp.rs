@@ -0,0 +1,446 @@
+//! A language server, so that editors can show errors, docs and symbols as a program is written.
+//!
+//! It speaks the Language Server Protocol over standard input and output and supports
+//! diagnostics, hover, go to definition, find references and completion of mnemonics and symbols.
+use std::{collections::HashMap,io,path::PathBuf};
+use rasm::{assemble,assemble_from,lint,Diagnostic,Program,Severity};
+use rasm::tooling::{docs,source};
+use crate::json::{self,Json};
+
+/// An open file and the last version of it that assembled
+struct Document {
+    text : String,
+    program : Option<Program>,
+}
+
+/// Serves requests until the client exits, returning whether it shut down cleanly
+pub fn run() -> bool {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    let mut documents : HashMap<String,Document> = HashMap::new();
+    let mut shutdown = false;
+    loop {
+        let message = match json::read_message(&mut input) {
+            Ok(Some(Ok(message))) => message,
+            Ok(Some(Err(e))) => {
+                eprintln!("lsp: {}",e);
+                continue
+            },
+            Ok(None) => return shutdown,
+            Err(e) => {
+                eprintln!("lsp: {}",e);
+                return false
+            }
+        };
+        let params = message.get("params");
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("").to_string();
+        let mut notifications = Vec::new();
+        let result = match message.get("method").as_str().unwrap_or("") {
+            "initialize" => Ok(capabilities()),
+            "initialized" => Ok(Json::Null),
+            "shutdown" => {
+                shutdown = true;
+                Ok(Json::Null)
+            },
+            "exit" => return shutdown,
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str().unwrap_or("").to_string();
+                notifications.push(update(&mut documents,&uri,text));
+                Ok(Json::Null)
+            },
+            "textDocument/didChange" => {
+                //only full syncs are asked for so the last change is the whole text
+                if let Some(text) = params.get("contentChanges").as_array().last().and_then(|c| c.get("text").as_str()) {
+                    notifications.push(update(&mut documents,&uri,text.to_string()));
+                }
+                Ok(Json::Null)
+            },
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                notifications.push(publish(&uri,Vec::new()));
+                Ok(Json::Null)
+            },
+            method @ ("textDocument/hover" | "textDocument/definition"
+                | "textDocument/references" | "textDocument/completion") => {
+                let line = params.get("position").get("line").as_u64().unwrap_or(0) as usize;
+                let character = params.get("position").get("character").as_u64().unwrap_or(0) as usize;
+                Ok(match documents.get(&uri) {
+                    Some(doc) => match method {
+                        "textDocument/hover"      => hover(doc,line,character),
+                        "textDocument/definition" => definition(doc,&uri,line,character),
+                        "textDocument/references" => {
+                            let declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                            references(doc,&uri,line,character,declaration)
+                        },
+                        _ => completion(doc,line,character),
+                    },
+                    None => Json::Null,
+                })
+            },
+            method => Err(format!("'{}' is not supported",method)),
+        };
+        for notification in notifications {
+            send(&mut output,&notification);
+        }
+        //notifications have no id and get no response
+        let id = message.get("id");
+        if *id == Json::Null {
+            continue;
+        }
+        let response = match result {
+            Ok(result) => Json::object(vec![("jsonrpc","2.0".into()),("id",id.clone()),("result",result)]),
+            Err(message) => Json::object(vec![
+                ("jsonrpc","2.0".into()),
+                ("id",id.clone()),
+                ("error",Json::object(vec![("code",Json::from(-32601i64)),("message",message.into())])),
+            ]),
+        };
+        send(&mut output,&response);
+    }
+}
+
+fn send(output : &mut impl io::Write, message : &Json) {
+    if let Err(e) = json::write_message(output,message) {
+        eprintln!("lsp: {}",e);
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        ("capabilities",Json::object(vec![
+            ("textDocumentSync",Json::from(1usize)),
+            ("hoverProvider",true.into()),
+            ("definitionProvider",true.into()),
+            ("referencesProvider",true.into()),
+            ("completionProvider",Json::object(vec![])),
+        ])),
+        ("serverInfo",Json::object(vec![("name","rasm-cli".into()),("version",env!("CARGO_PKG_VERSION").into())])),
+    ])
+}
+
+/// Reassembles a document after it changes, returning its new diagnostics
+fn update(documents : &mut HashMap<String,Document>, uri : &str, text : String) -> Json {
+    //included files are found relative to the document when it is saved somewhere
+    let assembled = match path(uri) {
+        Some(path) => assemble_from(&text,&path),
+        None       => assemble(&text),
+    };
+    let (program,diagnostics) = match assembled {
+        Ok(program) => {
+            let warnings = lint(&program);
+            (Some(program),warnings)
+        },
+        Err(errors) => (None,errors.0),
+    };
+    let diagnostics = diagnostics.iter().map(|d| to_lsp(&text,d)).collect();
+    let doc = documents.entry(uri.to_string()).or_insert(Document {text : String::new(), program : None});
+    doc.text = text;
+    if program.is_some() {
+        doc.program = program;
+    }
+    publish(uri,diagnostics)
+}
+
+/// The file a `file://` URI names, None for a document that isn't saved as a file
+fn path(uri : &str) -> Option<PathBuf> {
+    //only local files, which have an empty authority
+    let path = uri.strip_prefix("file://")?;
+    if !path.starts_with('/') {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    let digit = |d : u8| (d as char).to_digit(16).unwrap() as u8;
+    while let Some((&b,after)) = rest.split_first() {
+        match after {
+            [high,low,after @ ..] if b == b'%' && high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                bytes.push(digit(*high) << 4 | digit(*low));
+                rest = after;
+            },
+            _ => {
+                bytes.push(b);
+                rest = after;
+            },
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    //a Windows path, file:///C:/dir/file.rasm, has no '/' before the drive letter
+    let drive = path.as_bytes().get(1).is_some_and(u8::is_ascii_alphabetic) && path.as_bytes().get(2) == Some(&b':');
+    Some(PathBuf::from(if drive {&path[1..]} else {&path[..]}))
+}
+
+fn publish(uri : &str, diagnostics : Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc","2.0".into()),
+        ("method","textDocument/publishDiagnostics".into()),
+        ("params",Json::object(vec![("uri",uri.into()),("diagnostics",Json::Array(diagnostics))])),
+    ])
+}
+
+fn to_lsp(text : &str, d : &Diagnostic) -> Json {
+    //problems in included files are shown at the top, saying where they are
+    let (line_no,message) = match &d.file {
+        Some(file) => (0,format!("{}:{}: {}",file.display(),d.line + 1,d.message)),
+        None       => (d.line,d.message.clone()),
+    };
+    let line = text.lines().nth(line_no).unwrap_or("");
+    //columns are into the line the problem is on, which isn't this one for an included file
+    let columns = d.columns.clone().filter(|c| d.file.is_none() && line.get(c.clone()).is_some());
+    let severity = match d.severity {
+        Severity::Error   => 1usize,
+        Severity::Warning => 2,
+    };
+    Json::object(vec![
+        ("range",range(line_no,line,columns.unwrap_or(0..line.len()))),
+        ("severity",severity.into()),
+        ("source","rasm".into()),
+        ("message",message.into()),
+    ])
+}
+
+/// A range on one line, LSP counts columns in UTF-16 code units
+fn range(line_no : usize, line : &str, bytes : std::ops::Range<usize>) -> Json {
+    let position = |byte : usize| Json::object(vec![
+        ("line",line_no.into()),
+        ("character",line[..byte].encode_utf16().count().into()),
+    ]);
+    Json::object(vec![("start",position(bytes.start)),("end",position(bytes.end))])
+}
+
+fn location(uri : &str, line_no : usize, line : &str, bytes : std::ops::Range<usize>) -> Json {
+    Json::object(vec![("uri",uri.into()),("range",range(line_no,line,bytes))])
+}
+
+/// The byte offset of a UTF-16 column
+fn byte_at(line : &str, character : usize) -> usize {
+    let mut units = 0;
+    for (i,c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// What is under the cursor
+enum Target<'a> {
+    Mnemonic(&'a str),
+    /// A name in the operand or a label being defined
+    Symbol(&'a str),
+    /// Somewhere else in the operand of the given mnemonic
+    Operand(&'a str,&'a str),
+}
+
+fn target(line : &str, byte : usize) -> Option<Target<'_>> {
+    let spans = source::spans(line);
+    let contains = |r : &std::ops::Range<usize>| r.start <= byte && byte <= r.end;
+    if let Some(label) = spans.label.filter(contains) {
+        return Some(Target::Symbol(&line[label]));
+    }
+    let mnemonic = spans.mnemonic?;
+    if contains(&mnemonic) {
+        return Some(Target::Mnemonic(&line[mnemonic]));
+    }
+    let operand = spans.operand.filter(contains)?;
+    let text = &line[operand.clone()];
+    for r in source::identifiers(text) {
+        if r.start + operand.start <= byte && byte <= r.end + operand.start {
+            return Some(Target::Symbol(&text[r]));
+        }
+    }
+    Some(Target::Operand(&line[mnemonic],text))
+}
+
+fn hover(doc : &Document, line_no : usize, character : usize) -> Json {
+    let line = doc.text.lines().nth(line_no).unwrap_or("");
+    let markdown = match target(line,byte_at(line,character)) {
+        Some(Target::Mnemonic(m)) => match docs::mnemonic(m) {
+            Some((syntax,about)) => format!("```\n{}\n```\n{}",syntax,about),
+            None => return Json::Null,
+        },
+        Some(Target::Symbol(name)) => match describe(doc,name) {
+            Some(about) => about,
+            None => return Json::Null,
+        },
+        Some(Target::Operand(mnemonic,operand)) => match docs::addressing_mode(mnemonic,operand) {
+            Some(mode) => mode.to_string(),
+            None => return Json::Null,
+        },
+        None => return Json::Null,
+    };
+    Json::object(vec![("contents",Json::object(vec![("kind","markdown".into()),("value",markdown.into())]))])
+}
+
+/// What a symbol is, from the last version of the program that assembled
+fn describe(doc : &Document, name : &str) -> Option<String> {
+    let table = doc.program.as_ref()?.symbols();
+    if let Some((_,addr,line)) = table.labels().find(|&(n,_,_)| n == name) {
+        return Some(format!("label `{}`: instruction {}, defined on line {}",name,addr,line + 1));
+    }
+    if let Some(value) = table.constant(name) {
+        return Some(format!("constant `{}` = {}",name,value));
+    }
+    match name {
+        "ACC" => Some(String::from("ACC, the accumulator")),
+        "IX"  => Some(String::from("IX, the index register")),
+        _ => table.vars().find(|&(n,_)| n == name).map(|(_,addr)| format!("variable `{}`: memory address {}",name,addr)),
+    }
+}
+
+fn definition(doc : &Document, uri : &str, line_no : usize, character : usize) -> Json {
+    let line = doc.text.lines().nth(line_no).unwrap_or("");
+    let name = match target(line,byte_at(line,character)) {
+        Some(Target::Symbol(name)) => name,
+        _ => return Json::Null,
+    };
+    let lines : Vec<&str> = doc.text.lines().collect();
+    //labels, data declarations and constants are defined where they are declared, and any other
+    //variable by its first use
+    let declared = |i : usize| source::spans(lines[i]).label.filter(|l| &lines[i][l.clone()] == name);
+    let found = (0..lines.len()).find_map(|i| Some((i,declared(i)?)))
+        .or_else(|| (0..lines.len()).find_map(|i| Some((i,source::references(lines[i],name).into_iter().next()?))));
+    match found {
+        Some((i,r)) => location(uri,i,lines[i],r),
+        None => Json::Null,
+    }
+}
+
+fn references(doc : &Document, uri : &str, line_no : usize, character : usize, declaration : bool) -> Json {
+    let line = doc.text.lines().nth(line_no).unwrap_or("");
+    let name = match target(line,byte_at(line,character)) {
+        Some(Target::Symbol(name)) => name,
+        _ => return Json::Null,
+    };
+    let mut found = Vec::new();
+    for (i,line) in doc.text.lines().enumerate() {
+        let label = source::spans(line).label;
+        for r in source::references(line,name) {
+            if declaration || label.as_ref() != Some(&r) {
+                found.push(location(uri,i,line,r));
+            }
+        }
+    }
+    Json::Array(found)
+}
+
+fn completion(doc : &Document, line_no : usize, character : usize) -> Json {
+    let line = doc.text.lines().nth(line_no).unwrap_or("");
+    let byte = byte_at(line,character);
+    let spans = source::spans(line);
+    if spans.comment.is_some_and(|c| c.start < byte) {
+        return Json::Array(Vec::new());
+    }
+    //the operand is being written once the cursor is past the mnemonic, whether or not a label
+    //comes before it, or in the values of a data declaration
+    let in_operand = match (spans.mnemonic,spans.operand) {
+        (Some(mnemonic),_)    => byte > mnemonic.end,
+        (None,Some(operand))  => byte >= operand.start,
+        (None,None)           => false,
+    };
+    let items = if in_operand {
+        let table = match &doc.program {
+            Some(program) => program.symbols(),
+            None => return Json::Array(Vec::new()),
+        };
+        let labels = table.labels().map(|(name,_,line)| (name,3usize,format!("label on line {}",line + 1)));
+        let vars = table.vars().map(|(name,addr)| (name,6usize,format!("variable at address {}",addr)));
+        let constants = table.constants().map(|(name,value)| (name,21usize,format!("constant {}",value)));
+        labels.chain(vars).chain(constants)
+            .map(|(name,kind,detail)| Json::object(vec![
+                ("label",name.into()),
+                ("kind",kind.into()),
+                ("detail",detail.into()),
+            ]))
+            .collect()
+    } else {
+        docs::MNEMONICS.iter()
+            .map(|&(mnemonic,syntax,about)| Json::object(vec![
+                ("label",mnemonic.into()),
+                ("kind",14usize.into()),
+                ("detail",syntax.into()),
+                ("documentation",about.into()),
+            ]))
+            .collect()
+    };
+    Json::Array(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text : &str) -> Document {
+        Document {text : text.to_string(), program : assemble(text).ok()}
+    }
+
+    /// Where going to the definition of what is at `character` on `line` ends up
+    fn goto(text : &str, line : usize, character : usize) -> Option<(u64,u64)> {
+        let found = definition(&document(text),"file:///test.rasm",line,character);
+        let start = found.get("range").get("start");
+        Some((start.get("line").as_u64()?,start.get("character").as_u64()?))
+    }
+
+    /// The kinds of what is offered at `character` on the first line, 14 for a mnemonic
+    fn completed(text : &str, character : usize) -> Vec<u64> {
+        let items = completion(&document(text),0,character);
+        let mut kinds : Vec<u64> = items.as_array().iter().filter_map(|i| i.get("kind").as_u64()).collect();
+        kinds.dedup();
+        kinds
+    }
+
+    #[test]
+    fn completion_of_mnemonics() {
+        assert_eq!(completed("LDD X\nX: #5",2),[14]);
+        assert_eq!(completed("LOOP: LDD X\nJMP LOOP\nX: #5\nMAX EQU 3",8),[14]);
+        assert_eq!(completed("LOOP: LDD X\nJMP LOOP",5),[14]);
+        assert_eq!(completed("LOOP: LDD X\nJMP LOOP",6),[14]);
+    }
+
+    #[test]
+    fn completion_of_symbols() {
+        //labels, then variables, then constants
+        assert_eq!(completed("LOOP: LDD X\nJMP LOOP\nX: #5\nMAX EQU 3",10),[3,6,21]);
+        assert_eq!(completed("LDD X\nX: #5",4),[6]);
+        assert_eq!(completed("Y: #MAX\nMAX EQU 3",4),[6,21]);
+        assert_eq!(completed("LDD X ; the value\nX: #5",10),Vec::<u64>::new());
+    }
+
+    #[test]
+    fn paths_of_uris() {
+        let path = |uri| path(uri).map(|p| p.to_string_lossy().into_owned());
+        assert_eq!(path("file:///home/me/prog.rasm").as_deref(),Some("/home/me/prog.rasm"));
+        assert_eq!(path("file:///home/me/my%20prog.rasm").as_deref(),Some("/home/me/my prog.rasm"));
+        assert_eq!(path("file:///home/%C3%A9l%C3%A8ve/a.rasm").as_deref(),Some("/home/élève/a.rasm"));
+        assert_eq!(path("file:///C:/Users/me/a.rasm").as_deref(),Some("C:/Users/me/a.rasm"));
+        assert_eq!(path("file:///c%3A/Users/me/a.rasm").as_deref(),Some("c:/Users/me/a.rasm"));
+        assert_eq!(path("file:///100%/a.rasm").as_deref(),Some("/100%/a.rasm"));
+        assert_eq!(path("file:///%+1/a.rasm").as_deref(),Some("/%+1/a.rasm"));
+        assert_eq!(path("file://server/share/a.rasm"),None);
+        assert_eq!(path("untitled:Untitled-1"),None);
+    }
+
+    #[test]
+    fn definition_of_a_label() {
+        assert_eq!(goto("JMP DONE\nDONE: END",0,5),Some((1,0)));
+        assert_eq!(goto("JMP DONE\nDONE:\nEND",0,5),Some((1,0)));
+    }
+
+    #[test]
+    fn definition_of_a_data_declaration() {
+        assert_eq!(goto("LDD X\nADD X\nEND\nX: #5",1,4),Some((3,0)));
+    }
+
+    #[test]
+    fn definition_of_a_constant() {
+        assert_eq!(goto("LDM #MAX\nEND\nMAX EQU 10",0,6),Some((2,0)));
+        assert_eq!(goto("LDM #MAX\nEND\n.const MAX 10",0,6),Some((2,7)));
+    }
+
+    #[test]
+    fn definition_of_a_variable_is_its_first_use() {
+        assert_eq!(goto("LDM #1\nSTO X\nLDD X\nEND",2,4),Some((1,4)));
+        assert_eq!(goto("LDM #1\nEND",0,1),None);
+    }
+}
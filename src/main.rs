@@ -1,75 +1,43 @@
-use std::{borrow::Cow, env, path::PathBuf};
-mod rasm;
+use std::env;
+mod batch;
+mod cli;
+mod commands;
+mod dap;
+mod json;
+mod lsp;
+mod repl;
+mod stepper;
+use cli::Command;
 fn main() {
-    let envargs = match handle_args(env::args()) {
-        Ok(f) => f,
+    let command = match cli::parse(env::args()) {
+        Ok(command) => command,
         Err(e) => {
-            println!("{}",&e);
+            println!("{}",e.message);
             println!("Exiting due to above errors");
-            println!("{}",USAGE);
+            println!("{}",e.usage);
             std::process::exit(1)
         }
     };
 
-    rasm::run(envargs);
-
-}
-
-const USAGE : &str =
-r"
-USAGE:
-    ./rasm-cli.exe <options> <Path to .rasm file>
-OPTIONS:
-    -h | --help : help
-    -b | --binary : show acc and ix in binary 
-    -x | --hex : show acc and ix in hexadecimal
-Note:
-    Vertical bar '|' means 'or'
-
-";
-#[derive(Clone, Copy)]
-pub enum DisplayStyle {
-    Denary,
-    Binary,
-    Hex,
-}
-pub struct EnvArgs {
-    file : PathBuf,
-    style : DisplayStyle,
-
-}
-fn handle_args<'a>(mut args : env::Args) -> Result<EnvArgs,Cow<'static,str>> {
-    
-    let mut file = PathBuf::default();
-    let mut style = DisplayStyle::Denary;
-    args.next().unwrap();
-    for arg in args {
-        if arg.starts_with("-") {
-            style = match &arg[1..] {
-                "h" | "-help" => {
-                    println!("{}",USAGE);
-                    std::process::exit(1);
-                },
-                "b" | "-bin"  => {
-                    DisplayStyle::Binary
-                },
-                "x" | "-hex"  => {
-                    DisplayStyle::Hex
-                },
-                _   => {
-                    return Err(Cow::Borrowed("Unkown command line flag"));
-                    
-                }
-            }
-        }else if arg.ends_with(".rasm") || arg.ends_with(".asm") {
-            file = arg.into();
-        }
-        
-    }
-    if !file.exists() {
-        Err(Cow::Borrowed("File specified could not be found or unknown commandline argument"))
-    }else {
-        Ok(EnvArgs {file,style})
+    let ok = match command {
+        Command::Help(usage) => {
+            println!("{}",usage);
+            true
+        },
+        Command::Run(args)      => commands::run(args),
+        Command::Step(args)     => stepper::run(args),
+        Command::Trace(args)    => commands::trace(args),
+        Command::Check(args)    => commands::check(args),
+        Command::Assemble(args) => commands::assemble_to_file(args),
+        Command::Disasm(file)   => commands::disasm(&file),
+        Command::Fmt(args)      => commands::fmt(args),
+        Command::Test(args)     => batch::run(args),
+        Command::Lsp            => lsp::run(),
+        Command::Dap            => dap::run(),
+        Command::Repl(args)     => repl::run(args),
+    };
+    if !ok {
+        std::process::exit(1)
     }
 
 }
@@ -0,0 +1,192 @@
+//! Where control can go after each instruction of a program.
+use super::{Code,Instruction};
+
+/// The addresses execution can continue at after the instruction at `pc`.
+/// An address of `code.code.len()` means running past the last instruction.
+pub fn successors(code : &Code, pc : usize) -> Vec<usize> {
+    match code.code[pc] {
+        Instruction::END => vec![],
+        Instruction::JMP {addr} => vec![addr as usize],
+        Instruction::JPE {addr} | Instruction::JPN {addr} => {
+            if addr as usize == pc + 1 {
+                vec![pc + 1]
+            } else {
+                vec![pc + 1,addr as usize]
+            }
+        },
+        _ => vec![pc + 1],
+    }
+}
+
+/// Whether each instruction can be reached by starting from the first one
+pub fn reachable(code : &Code) -> Vec<bool> {
+    let mut seen = vec![false;code.code.len()];
+    let mut todo = vec![code.start()];
+    while let Some(pc) = todo.pop() {
+        match seen.get_mut(pc) {
+            Some(s) if !*s => *s = true,
+            _ => continue,
+        }
+        todo.extend(successors(code,pc));
+    }
+    seen
+}
+
+/// A run of instructions that is only ever entered at the top and left at the bottom
+pub struct Block {
+    pub start : usize,
+    /// One past the last instruction in the block
+    pub end : usize,
+}
+
+/// Splits a program into basic blocks, a new block starts at every label and jump target
+/// and after every JMP, JPE, JPN and END. Each address `ORG` skipped over is a block of its own
+pub fn blocks(code : &Code) -> Vec<Block> {
+    let len = code.code.len();
+    let mut leaders = vec![false;len + 1];
+    leaders[0] = true;
+    leaders[len] = true;
+    for (_,addr,_) in code.table.labels() {
+        leaders[addr.min(len)] = true;
+    }
+    for (pc,instr) in code.code.iter().enumerate() {
+        match *instr {
+            Instruction::JMP {addr} | Instruction::JPE {addr} | Instruction::JPN {addr} => {
+                leaders[(addr as usize).min(len)] = true;
+                leaders[pc + 1] = true;
+            },
+            Instruction::END => leaders[pc + 1] = true,
+            _ => (),
+        }
+        if code.is_gap(pc) {
+            leaders[pc] = true;
+        }
+    }
+    let starts : Vec<usize> = (0..=len).filter(|&pc| leaders[pc]).collect();
+    starts.windows(2).map(|w| Block {start : w[0], end : w[1]}).collect()
+}
+
+/// The control flow graph as a Graphviz DOT graph, with a box for each basic block
+pub fn to_dot(code : &Code) -> String {
+    let len = code.code.len();
+    let blocks = blocks(code);
+    let block_at = |pc : usize| {
+        if pc >= len {
+            String::from("end")
+        } else if code.is_gap(pc) {
+            String::from("gap")
+        } else {
+            format!("b{}",blocks.iter().position(|b| b.start <= pc && pc < b.end).unwrap())
+        }
+    };
+    let mut dot = String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut runs_off_end = false;
+    let mut runs_into_gap = false;
+    for (i,block) in blocks.iter().enumerate() {
+        if code.is_gap(block.start) {
+            continue;
+        }
+        let mut label = String::new();
+        let mut names : Vec<&str> = code.table.labels()
+            .filter(|&(_,addr,_)| addr == block.start)
+            .map(|(name,_,_)| name)
+            .collect();
+        names.sort_unstable();
+        for name in names {
+            label.push_str(&format!("{}:\\l",escape(name)));
+        }
+        for pc in block.start..block.end {
+            let info = &code.debug_info[pc];
+            label.push_str(&format!("{:>3}: {}\\l",info.line + 1,escape(&info.text)));
+        }
+        dot.push_str(&format!("    b{} [label=\"{}\"];\n",i,label));
+
+        let last = block.end - 1;
+        let next = successors(code,last);
+        let edge_labels : &[&str] = match code.code[last] {
+            Instruction::JPE {..} | Instruction::JPN {..} if next.len() == 2 => &["not taken","taken"],
+            _ => &[],
+        };
+        for (j,target) in next.iter().enumerate() {
+            runs_off_end |= *target >= len;
+            runs_into_gap |= code.is_gap(*target);
+            match edge_labels.get(j) {
+                Some(l) => dot.push_str(&format!("    b{} -> {} [label=\"{}\"];\n",i,block_at(*target),l)),
+                None    => dot.push_str(&format!("    b{} -> {};\n",i,block_at(*target))),
+            }
+        }
+    }
+    if runs_off_end {
+        dot.push_str("    end [label=\"past the last instruction\", shape=ellipse, color=red];\n");
+    }
+    if runs_into_gap {
+        dot.push_str("    gap [label=\"an address ORG skipped over\", shape=ellipse, color=red];\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn escape(s : &str) -> String {
+    s.replace('\\',"\\\\").replace('"',"\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts ACC up to 3 and then outputs it
+    const BRANCHING : &str = "LDM #0\nLOOP: INC ACC\nCMP #3\nJPN LOOP\nOUT\nEND";
+
+    fn code(src : &str) -> Code {
+        crate::assemble(src).unwrap()
+    }
+
+    #[test]
+    fn edges() {
+        let code = code(BRANCHING);
+        let edges : Vec<Vec<usize>> = (0..code.code.len()).map(|pc| successors(&code,pc)).collect();
+        assert_eq!(edges,vec![vec![1],vec![2],vec![3],vec![4,1],vec![5],vec![]]);
+    }
+
+    #[test]
+    fn jump_to_the_next_instruction_is_one_edge() {
+        let code = code("CMP #0\nJPE NEXT\nNEXT: END");
+        assert_eq!(successors(&code,1),vec![2]);
+    }
+
+    #[test]
+    fn reachability() {
+        assert_eq!(reachable(&code("JMP L\nOUT\nL: END\nIN")),vec![true,false,true,false]);
+        assert!(reachable(&code(BRANCHING)).iter().all(|&r| r));
+    }
+
+    #[test]
+    fn basic_blocks() {
+        let bounds = |src : &str| blocks(&code(src)).iter().map(|b| (b.start,b.end)).collect::<Vec<_>>();
+        assert_eq!(bounds(BRANCHING),vec![(0,1),(1,4),(4,6)]);
+        //each address ORG skips is a block of its own
+        assert_eq!(bounds("JMP L\nORG 3\nL: END"),vec![(0,1),(1,2),(2,3),(3,4)]);
+    }
+
+    #[test]
+    fn dot() {
+        assert_eq!(to_dot(&code(BRANCHING)),"\
+digraph program {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"  1: LDM #0\\l\"];
+    b0 -> b1;
+    b1 [label=\"LOOP:\\l  2: INC ACC\\l  3: CMP #3\\l  4: JPN LOOP\\l\"];
+    b1 -> b2 [label=\"not taken\"];
+    b1 -> b1 [label=\"taken\"];
+    b2 [label=\"  5: OUT\\l  6: END\\l\"];
+}
+");
+    }
+
+    #[test]
+    fn dot_shows_running_off_the_end() {
+        let dot = to_dot(&code("CMP #'\"'\nJPE L\nEND\nL: OUT"));
+        assert!(dot.contains("1: CMP #'\\\"'\\l"),"{}",dot);
+        assert!(dot.contains("b2 -> end;\n    end [label=\"past the last instruction\""),"{}",dot);
+    }
+}
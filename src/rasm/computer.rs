@@ -16,7 +16,7 @@ pub struct ComputerBuilder<I : Read,O : Write, const N: usize>  {
     disp_style : Option<DisplayStyle>,
     cpu        : Option<CPU<I,O,N>>
 }
-impl<'a,I : Read,O : Write, const N: usize> ComputerBuilder<I ,O , N> {
+impl<I : Read,O : Write, const N: usize> ComputerBuilder<I ,O , N> {
     pub fn new() -> Self {
 
         ComputerBuilder {
@@ -43,7 +43,18 @@ impl<'a,I : Read,O : Write, const N: usize> ComputerBuilder<I ,O , N> {
         })
     }
 }
-impl<'a,I : Read,O : Write, const N: usize> Display for Computer<I,O,N> {
+impl<I : Read,O : Write, const N: usize> Computer<I,O,N> {
+    pub fn set_display_style(&mut self, style : DisplayStyle) {
+        self.disp_style = style;
+        self.cpu.memory.set_display_style(style);
+    }
+}
+impl<I : Read,O : Write, const N: usize> Default for ComputerBuilder<I ,O , N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<I : Read,O : Write, const N: usize> Display for Computer<I,O,N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
     match self.disp_style{
 
@@ -71,6 +82,24 @@ r"
                self.cpu.ix() )
 
 
+    },
+    DisplayStyle::All => {
+        let row = |name : &str, value : i16| format!("|  {:<4} {}  |\n",name,self.disp_style.format(value));
+        let width = row("",0).len() - 3;
+        writeln!(f)?;
+        writeln!(f," {:-<w$}","",w = width)?;
+        writeln!(f,"|{:^w$}|","CPU",w = width)?;
+        writeln!(f,"|  {:<4} {}  |","",DisplayStyle::ALL_COLUMNS)?;
+        f.write_str(&row("ACC",self.cpu.acc()))?;
+        f.write_str(&row("IX",self.cpu.ix()))?;
+        f.write_str(&row("PC",self.cpu.pc() as i16))?;
+        let flags = format!("FLAGS  N V Z C = {} {} {} {}",
+            self.cpu.get_flag(FLAGS::N) as u8,
+            self.cpu.get_flag(FLAGS::V) as u8,
+            self.cpu.get_flag(FLAGS::Z) as u8,
+            self.cpu.get_flag(FLAGS::C) as u8);
+        writeln!(f,"|  {:<w$}|",flags,w = width - 2)?;
+        write!(f," {:-<w$} ","",w = width)
     },
     DisplayStyle::Binary => {
        write!(f, 
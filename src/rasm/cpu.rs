@@ -1,8 +1,10 @@
 
-use super::{Instruction,AdrMode};
+use super::{Instruction,AdrMode,Register};
 
 use std::{
-    io::{Read,Write}
+    io::{Read,Write},
+    fmt::{self,Display,Formatter},
+    convert::TryFrom,
 };
 
 use super::mem::Memory;
@@ -15,6 +17,12 @@ where
     pc : u16,
     done : bool,
     flag_register : Flags,
+    /// Memory address register
+    pub(crate) mar : u16,
+    /// Memory data register
+    pub(crate) mdr : Word,
+    /// Current instruction register, empty until the first fetch of a micro-stepped cycle
+    pub(crate) cir : Option<Instruction>,
     pub memory : Memory<N>, 
     display    : O,
     input      : I
@@ -31,6 +39,9 @@ where
             pc  : 0,
             done : false,
             flag_register : Flags::new(),
+            mar : 0,
+            mdr : Word::Data(0),
+            cir : None,
             memory,
             display,
             input
@@ -48,42 +59,83 @@ where
     pub fn get_flag(&self,flag : FLAGS) -> bool {
         self.flag_register.get_flag(flag)
     }
+    /// All four flags packed into the low bits, see [`FLAGS`]
+    pub fn flags(&self) -> u8 {
+        self.flag_register.flags
+    }
     pub fn done(&self) -> bool {
         self.done
     }
-    pub fn execute(&mut self,instr : &Instruction) {
-        use Instruction::*;
+    pub fn mar(&self) -> u16 {
+        self.mar
+    }
+    pub fn mdr(&self) -> &Word {
+        &self.mdr
+    }
+    pub fn cir(&self) -> Option<&Instruction> {
+        self.cir.as_ref()
+    }
+    /// Where `OUT` writes to
+    pub fn output(&self) -> &O {
+        &self.display
+    }
+    pub fn execute(&mut self,instr : &Instruction) -> Result<(),Fault> {
         self.pc += 1;
+        self.run(instr)
+    }
+    /// Executes the instruction in the CIR, the fetch having already moved the PC on
+    pub(crate) fn execute_cir(&mut self) -> Result<(),Fault> {
+        let instr = self.cir.clone().ok_or(Fault::EmptyCir)?;
+        self.run(&instr)
+    }
+    pub fn set_pc(&mut self, pc : u16) {
+        self.pc = pc;
+    }
+    /// Lets a CPU that has reached END carry on, from wherever the PC is set
+    pub fn clear_done(&mut self) {
+        self.done = false;
+    }
+    pub(crate) fn increment_pc(&mut self) {
+        self.pc += 1;
+    }
+    fn run(&mut self,instr : &Instruction) -> Result<(),Fault> {
+        use Instruction::*;
         match *instr {
-            LOAD{data,adr_mode} => self.load(data, adr_mode),
+            LOAD{data,adr_mode} => self.load(data, adr_mode)?,
             LDR(x)              => self.ldr(x),
-            MOV                 => self.ix = self.acc,
-            IO(b)               => self.io(b),
-            SUB{rhs,adr_mode}   => self.sub(rhs as i16,adr_mode),
-            STO(addr)           => self.sto(addr),
-            ADD{rhs,adr_mode}   => self.add(rhs as i16,adr_mode),
+            MOV(r)              => self.set(r,self.acc),
+            IO(b)               => self.io(b)?,
+            SUB{rhs,adr_mode}   => self.sub(rhs as i16,adr_mode)?,
+            STO(addr)           => self.sto(addr)?,
+            ADD{rhs,adr_mode}   => self.add(rhs as i16,adr_mode)?,
             INC(b)              => self.addn(b,1),
             DEC(b)              => self.addn(b,-1),
             LSL(imm)            => self.lsl(imm),
             LSR(imm)            => self.lsr(imm),
-            XOR {rhs,adr_mode}  => self.xor(rhs,adr_mode),
-            OR {rhs,adr_mode}   => self.or(rhs,adr_mode),
-            CMP {rhs,adr_mode}  => self.cmp(rhs as i16,adr_mode), 
+            XOR {rhs,adr_mode}  => self.xor(rhs,adr_mode)?,
+            OR {rhs,adr_mode}   => self.or(rhs,adr_mode)?,
+            CMP {rhs,adr_mode}  => self.cmp(rhs as i16,adr_mode)?,
             END                 => self.end(),
-            UNKNOWN             => eprintln!("Unkown command!"),
             _                   => self.jmp(instr),
         }
+        Ok(())
     }
-    fn load(&mut self,data : u16,addressing_mode : AdrMode) {
+    fn read(&self, addr : i32) -> Result<i16,Fault> {
+        usize::try_from(addr).ok()
+            .and_then(|a| self.memory.get(a))
+            .ok_or(Fault::BadAddress(addr))
+    }
+    fn load(&mut self,data : u16,addressing_mode : AdrMode) -> Result<(),Fault> {
         match addressing_mode {
             AdrMode::Indirect => {
-                self.acc = self.memory[self.memory[data as usize] as usize];
+                let addr = self.read(data as i32)?;
+                self.acc = self.read(addr as i32)?;
             },
             AdrMode::Direct   => {
-                self.acc = self.memory[data as usize];
+                self.acc = self.read(data as i32)?;
             },
             AdrMode::Indexed  => {
-                self.acc = self.memory[(data as i16 + self.ix) as usize];
+                self.acc = self.read(data as i32 + self.ix as i32)?;
             },
             AdrMode::Immediate => {
                 self.acc = data as i16;
@@ -91,98 +143,124 @@ where
 
         }
         self.flag_register.set_flags(Some(self.acc));
+        Ok(())
     }
     fn lsl(&mut self, n : u16) {
         self.flag_register.set_flag(FLAGS::C,(self.acc as u16 & 0x8000u16) != 0 );
-        let x = (self.acc as u16) << n;
+        let x = (self.acc as u16).checked_shl(n as u32).unwrap_or(0);
         self.acc = x as i16;
     }
     fn lsr(&mut self, n : u16) {
         self.flag_register.set_flag(FLAGS::C, (self.acc & 0x1) != 0);
-        let x = self.acc as u16 >> n;
+        let x = (self.acc as u16).checked_shr(n as u32).unwrap_or(0);
         self.acc = x as i16;
     }
     fn ldr(&mut self,imm : i16) {
         self.ix = imm;
     }
-    fn xor(&mut self, imm : u16,adr_mode : AdrMode) {
-        let imm = self.get_data(imm, adr_mode);
+    fn xor(&mut self, imm : u16,adr_mode : AdrMode) -> Result<(),Fault> {
+        let imm = self.get_data(imm, adr_mode)?;
         self.flag_register.set_flags(Some(self.acc ^ imm));
         self.acc ^= imm ;
+        Ok(())
     }
-    fn get_data(&self,i : u16,adr_mode : AdrMode) -> i16 {
+    fn get_data(&self,i : u16,adr_mode : AdrMode) -> Result<i16,Fault> {
         match adr_mode {
-            AdrMode::Immediate => i as i16,
-            AdrMode::Direct    => self.memory[i as usize],
+            AdrMode::Immediate => Ok(i as i16),
+            AdrMode::Direct    => self.read(i as i32),
             _                  => unreachable!(),
         }
     }
-    fn or(&mut self, imm : u16,adr_mode : AdrMode) {
-        let imm = self.get_data(imm,adr_mode);
+    fn or(&mut self, imm : u16,adr_mode : AdrMode) -> Result<(),Fault> {
+        let imm = self.get_data(imm,adr_mode)?;
         self.flag_register.set_flags(Some(self.acc | imm));
         self.acc |= imm;
-        
+        Ok(())
     }
-    fn io(&mut self, inp : bool) {
-        
+    fn io(&mut self, inp : bool) -> Result<(),Fault> {
+
         if inp {
-            
-            let mut buffer = [0;3];
-            self.input.read_exact(&mut buffer).unwrap();
-            self.acc = buffer[0] as char as i16;
+            //one character is read per line of input, an empty line gives a newline
+            let mut line = Vec::new();
+            let mut byte = [0;1];
+            loop {
+                match self.input.read(&mut byte) {
+                    Ok(0) if line.is_empty() => return Err(Fault::NoInput),
+                    Ok(0) => break,
+                    Ok(_) if byte[0] == b'\n' => break,
+                    Ok(_) if byte[0] == b'\r' => (),
+                    Ok(_) => line.push(byte[0]),
+                    Err(_) => return Err(Fault::NoInput),
+                }
+            }
+            self.acc = line.first().copied().unwrap_or(b'\n') as i16;
             self.flag_register.set_flags(Some(self.acc));
         }else {
             write!(&mut self.display,"{}",self.acc as u8 as char).unwrap();
+            self.display.flush().unwrap();
         }
+        Ok(())
     }
-    fn sub(&mut self, imm : i16, adr_mode : AdrMode) {
-        let imm = self.get_data(imm as u16, adr_mode);
+    fn sub(&mut self, imm : i16, adr_mode : AdrMode) -> Result<(),Fault> {
+        let imm = self.get_data(imm as u16, adr_mode)?;
 
         let res = self.acc.checked_sub(imm);
         self.flag_register.set_flags(res);
-        self.acc -= imm;
+        self.acc = self.acc.wrapping_sub(imm);
+        Ok(())
     }
 
-    fn sto(&mut self,addr : u16) {
-        self.memory[addr as usize] = self.acc;
+    fn sto(&mut self,addr : u16) -> Result<(),Fault> {
+        let acc = self.acc;
+        *self.memory.get_mut(addr as usize).ok_or(Fault::BadAddress(addr as i32))? = acc;
+        Ok(())
     }
-    fn add(&mut self, imm : i16,adr_mode : AdrMode) {
-        let imm = self.get_data(imm as u16, adr_mode);
+    fn add(&mut self, imm : i16,adr_mode : AdrMode) -> Result<(),Fault> {
+        let imm = self.get_data(imm as u16, adr_mode)?;
         self.flag_register.set_flags(
                 self.acc.checked_add(imm)
             );
-        self.acc += imm;
+        self.acc = self.acc.wrapping_add(imm);
+        Ok(())
     }
-    
-    fn addn(&mut self, acc : bool, n : i16) {
-        if acc {
-            self.flag_register.set_flags(self.acc.checked_add(n));
-            self.acc += n;
-        }else {
-            self.ix += n;
+
+    fn set(&mut self, register : Register, value : i16) {
+        match register {
+            Register::ACC => self.acc = value,
+            Register::IX  => self.ix = value,
+        }
+    }
+    fn addn(&mut self, register : Register, n : i16) {
+        match register {
+            Register::ACC => {
+                self.flag_register.set_flags(self.acc.checked_add(n));
+                self.acc = self.acc.wrapping_add(n);
+            },
+            Register::IX => self.ix = self.ix.wrapping_add(n),
         }
     }
-    fn cmp(&mut self,imm : i16,adr_mode : AdrMode) {
-        let imm = self.get_data(imm as u16, adr_mode);
+    fn cmp(&mut self,imm : i16,adr_mode : AdrMode) -> Result<(),Fault> {
+        let imm = self.get_data(imm as u16, adr_mode)?;
         self
             .flag_register.
-            set_flags(Some(self.acc - imm));
+            set_flags(self.acc.checked_sub(imm));
+        Ok(())
     }
 
     fn end(&mut self) {
-        println!("-------Program ending---------");
         self.done = true;
     }
     fn jmp(&mut self, instr : &Instruction) {
         let ni = match *instr {
-            Instruction::JPE {addr,..} => {
+            Instruction::JMP {addr} => addr,
+            Instruction::JPE {addr} => {
                 if self.flag_register.get_flag(FLAGS::Z) {
                     addr
                 }else {
-                    self.pc 
+                    self.pc
                 }
             },
-            Instruction::JPN {addr,..} => {
+            Instruction::JPN {addr} => {
                 if self.flag_register.get_flag(FLAGS::Z) {
                     self.pc
                 }else {
@@ -197,7 +275,49 @@ where
 
 }
 
+/// What the memory data register holds, an instruction while fetching and data otherwise
+#[derive(Clone)]
+pub enum Word {
+    Data(i16),
+    Instr(Instruction),
+}
+
+/// Something that stopped a program from running to its `END`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    BadAddress(i32),
+    NoInput,
+    RanOffEnd(u16),
+    /// PC reached an address that `ORG` skipped over
+    NoInstruction(u16),
+    StepLimit(usize),
+    /// Executing or decoding the CIR before an instruction was fetched into it
+    EmptyCir,
+    /// The machine came back to exactly the same state, `from`..=`to` are the source lines of the loop
+    InfiniteLoop {line : usize, from : usize, to : usize},
+}
+impl std::error::Error for Fault {}
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Fault::BadAddress(addr) => write!(f,"memory address {} is out of range",addr),
+            Fault::NoInput          => write!(f,"IN executed but there was no input left"),
+            Fault::RanOffEnd(pc)    => write!(f,"ran past the last instruction (PC = {}), remember to add 'END'",pc),
+            Fault::NoInstruction(pc) => write!(f,"there is no instruction at address {}, ORG skipped over it",pc),
+            Fault::StepLimit(n)     => write!(f,"gave up after {} steps, the program may be stuck in a loop",n),
+            Fault::EmptyCir         => write!(f,"there is no instruction in the CIR, one has to be fetched first"),
+            Fault::InfiniteLoop {line,from,to} if from == to => {
+                write!(f,"infinite loop detected at line {} (it repeats forever)",line + 1)
+            },
+            Fault::InfiniteLoop {line,from,to} => {
+                write!(f,"infinite loop detected at line {} (lines {}-{} repeat forever)",line + 1,from + 1,to + 1)
+            },
+        }
+    }
+}
+
 //Bit masks
+#[derive(Clone, Copy)]
 pub enum FLAGS {
     Z = 0b1000,
     N = 0b0100,
@@ -0,0 +1,64 @@
+//! What each instruction and addressing mode does, worded the way the syllabus does.
+
+/// Every mnemonic with its syntax and a description
+pub const MNEMONICS : &[(&str,&str,&str)] = &[
+    ("LDM","LDM #n","Immediate addressing. Load the number n to ACC"),
+    ("LDD","LDD <address>","Direct addressing. Load the contents of the location at the given address to ACC"),
+    ("LDI","LDI <address>","Indirect addressing. The address to be used is at the given address. Load the contents of this second address to ACC"),
+    ("LDX","LDX <address>","Indexed addressing. Form the address from <address> + the contents of the index register. Copy the contents of this calculated address to ACC"),
+    ("LDR","LDR #n","Immediate addressing. Load the number n to IX"),
+    ("MOV","MOV <register>","Move the contents of the accumulator to the given register (IX)"),
+    ("STO","STO <address>","Store the contents of ACC at the given address"),
+    ("ADD","ADD <address> | ADD #n","Add the contents of the given address, or the number n, to the ACC"),
+    ("SUB","SUB <address> | SUB #n","Subtract the contents of the given address, or the number n, from the ACC"),
+    ("INC","INC <register>","Add 1 to the contents of the register (ACC or IX)"),
+    ("DEC","DEC <register>","Subtract 1 from the contents of the register (ACC or IX)"),
+    ("JMP","JMP <address>","Jump to the given address"),
+    ("CMP","CMP <address> | CMP #n","Compare the contents of ACC with the contents of the given address, or the number n"),
+    ("JPE","JPE <address>","Following a compare instruction, jump to <address> if the compare was True"),
+    ("JPN","JPN <address>","Following a compare instruction, jump to <address> if the compare was False"),
+    ("IN","IN","Key in a character and store its ASCII value in ACC"),
+    ("OUT","OUT","Output to the screen the character whose ASCII value is stored in ACC"),
+    ("END","END","Return control to the operating system"),
+    ("XOR","XOR <address> | XOR #n","Bitwise XOR of the contents of ACC with the contents of the given address, or the number n"),
+    ("OR","OR <address> | OR #n","Bitwise OR of the contents of ACC with the contents of the given address, or the number n"),
+    ("LSL","LSL #n","Bits in ACC are shifted logically n places to the left. Zeros are introduced on the right hand end"),
+    ("LSR","LSR #n","Bits in ACC are shifted logically n places to the right. Zeros are introduced on the left hand end"),
+];
+
+pub const IMMEDIATE : &str = "Immediate addressing: the operand is the value itself. \
+#n is denary (#-n if negative), #Bn or #0bn is binary, #&n or #0xn is hexadecimal and #'c' is a character";
+pub const DIRECT : &str = "Direct addressing: the operand is the address of the value in memory";
+pub const INDIRECT : &str = "Indirect addressing: the operand is the address of a location holding the address of the value";
+pub const INDEXED : &str = "Indexed addressing: the address of the value is the operand plus the contents of IX";
+pub const REGISTER : &str = "The operand is a register, ACC (the accumulator) or IX (the index register)";
+
+/// Directives that tell the assembler something rather than being assembled into instructions
+pub const DIRECTIVES : &[(&str,&str,&str)] = &[
+    ("EQU","NAME EQU n","Define the constant NAME as the number n, to be used as an immediate value like #NAME"),
+    (".CONST",".const NAME n","Define the constant NAME as the number n, the same as NAME EQU n"),
+    ("MACRO","MACRO NAME p1, p2","Start the definition of a macro, the lines up to ENDM are copied in wherever NAME a, b is used, with p1 and p2 replaced by a and b"),
+    ("ENDM","ENDM","End the definition of a macro"),
+    ("INCLUDE","INCLUDE \"file.rasm\"","Put the lines of another file here, found relative to this one"),
+    ("ORG","ORG n","Put the instructions after this from address n, and the variables declared after it from memory address n"),
+];
+
+/// The syntax and description of a mnemonic or directive, in any case
+pub fn mnemonic(name : &str) -> Option<(&'static str,&'static str)> {
+    let name = name.to_ascii_uppercase();
+    MNEMONICS.iter().chain(DIRECTIVES).find(|(m,_,_)| *m == name).map(|&(_,syntax,about)| (syntax,about))
+}
+
+/// How the operand of an instruction is used
+pub fn addressing_mode(mnemonic : &str, operand : &str) -> Option<&'static str> {
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    Some(match mnemonic.as_str() {
+        "IN" | "OUT" | "END" | "EQU" | ".CONST" | "ORG" => return None,
+        "INC" | "DEC" | "MOV"       => REGISTER,
+        "LDI"                       => INDIRECT,
+        "LDX"                       => INDEXED,
+        _ if operand.starts_with('#') || operand.starts_with('\'') => IMMEDIATE,
+        "LDM" | "LDR" | "LSL" | "LSR" => IMMEDIATE,
+        _                           => DIRECT,
+    })
+}
@@ -0,0 +1,110 @@
+//! Plain English descriptions of what an instruction did, with the values it used.
+use std::{convert::TryFrom,io::{Read,Write}};
+use super::{cpu::{CPU,FLAGS},AdrMode,Instruction,Register,SymbolTable};
+
+/// The registers and memory just before an instruction was executed
+pub struct Before {
+    acc : i16,
+    ix : i16,
+    zero : bool,
+    memory : Vec<i16>,
+}
+impl Before {
+    pub fn of<I : Read,O : Write,const N : usize>(cpu : &CPU<I,O,N>) -> Self {
+        Self {
+            acc : cpu.acc(),
+            ix : cpu.ix(),
+            zero : cpu.get_flag(FLAGS::Z),
+            memory : cpu.memory.cells().to_vec(),
+        }
+    }
+    fn read(&self, addr : i32) -> i16 {
+        usize::try_from(addr).ok().and_then(|a| self.memory.get(a)).copied().unwrap_or(0)
+    }
+}
+
+/// Describes what `instr` did, given the machine before it and the CPU after it
+pub fn explain<I : Read,O : Write,const N : usize>(instr : &Instruction, before : &Before, cpu : &CPU<I,O,N>, table : &SymbolTable) -> String {
+    let at = |addr : i32| format!("address {}{}",addr,var_name(table,addr));
+    //what an instruction that reads an operand got and where from
+    let operand = |data : u16, adr_mode : AdrMode| match adr_mode {
+        AdrMode::Immediate => format!("{}",data as i16),
+        _ => format!("the value {} from {}",before.read(data as i32),at(data as i32)),
+    };
+    let acc = before.acc;
+    let sentence = match *instr {
+        Instruction::LOAD {data, adr_mode : AdrMode::Immediate} => format!("Loaded the value {} into ACC",data as i16),
+        Instruction::LOAD {data, adr_mode : AdrMode::Direct} => format!("Loaded the value {} from {} into ACC",cpu.acc(),at(data as i32)),
+        Instruction::LOAD {data, adr_mode : AdrMode::Indirect} => {
+            let addr = before.read(data as i32);
+            format!("Read the address {} from {}, then loaded the value {} from {} into ACC",addr,at(data as i32),cpu.acc(),at(addr as i32))
+        },
+        Instruction::LOAD {data, adr_mode : AdrMode::Indexed} => {
+            let addr = data as i32 + before.ix as i32;
+            format!("Added IX ({}) to {} to get {}, then loaded the value {} from it into ACC",before.ix,at(data as i32),at(addr),cpu.acc())
+        },
+        Instruction::LDR(n) => format!("Loaded the value {} into IX",n),
+        Instruction::MOV(r) => format!("Copied ACC ({}) into {}",acc,r),
+        Instruction::STO(addr) => format!("Stored ACC ({}) at {}, replacing {}",acc,at(addr as i32),before.read(addr as i32)),
+        Instruction::ADD {rhs,adr_mode} => format!("Added {} to ACC ({}), giving {}",operand(rhs,adr_mode),acc,cpu.acc()),
+        Instruction::SUB {rhs,adr_mode} => format!("Subtracted {} from ACC ({}), giving {}",operand(rhs,adr_mode),acc,cpu.acc()),
+        Instruction::INC(Register::ACC) => format!("Added 1 to ACC ({}), giving {}",acc,cpu.acc()),
+        Instruction::INC(Register::IX) => format!("Added 1 to IX ({}), giving {}",before.ix,cpu.ix()),
+        Instruction::DEC(Register::ACC) => format!("Subtracted 1 from ACC ({}), giving {}",acc,cpu.acc()),
+        Instruction::DEC(Register::IX) => format!("Subtracted 1 from IX ({}), giving {}",before.ix,cpu.ix()),
+        Instruction::LSL(n) => format!("Shifted ACC ({:016b}) {} places to the left, giving {:016b} ({})",acc as u16,n,cpu.acc() as u16,cpu.acc()),
+        Instruction::LSR(n) => format!("Shifted ACC ({:016b}) {} places to the right, giving {:016b} ({})",acc as u16,n,cpu.acc() as u16,cpu.acc()),
+        Instruction::XOR {rhs,adr_mode} => format!("Combined ACC ({}) with {} using XOR, giving {}",acc,operand(rhs,adr_mode),cpu.acc()),
+        Instruction::OR {rhs,adr_mode} => format!("Combined ACC ({}) with {} using OR, giving {}",acc,operand(rhs,adr_mode),cpu.acc()),
+        Instruction::CMP {rhs,adr_mode} => {
+            let equal = if cpu.get_flag(FLAGS::Z) {"they are equal"} else {"they are not equal"};
+            format!("Compared ACC ({}) with {}, {}",acc,operand(rhs,adr_mode),equal)
+        },
+        Instruction::JMP {addr} => format!("Jumped to {}",instruction(table,addr)),
+        Instruction::JPE {addr} | Instruction::JPN {addr} => {
+            let comparison = if before.zero {"equal"} else {"not equal"};
+            if matches!(instr,Instruction::JPE {..}) == before.zero {
+                format!("The last comparison was {} so jumped to {}",comparison,instruction(table,addr))
+            } else {
+                format!("The last comparison was {} so carried on to the next instruction",comparison)
+            }
+        },
+        Instruction::IO(true) => format!("Read {} from the keyboard into ACC",character(cpu.acc())),
+        Instruction::IO(false) => format!("Output {}, the character in ACC",character(acc)),
+        Instruction::END => String::from("Ended the program"),
+    };
+    let flags : &[(&str,FLAGS)] = match instr {
+        Instruction::LOAD {..} | Instruction::ADD {..} | Instruction::SUB {..} | Instruction::INC(Register::ACC)
+        | Instruction::DEC(Register::ACC) | Instruction::XOR {..} | Instruction::OR {..} | Instruction::CMP {..}
+        | Instruction::IO(true) => &[("Z",FLAGS::Z),("N",FLAGS::N),("V",FLAGS::V),("C",FLAGS::C)],
+        Instruction::LSL(_) | Instruction::LSR(_) => &[("C",FLAGS::C)],
+        _ => &[],
+    };
+    if flags.is_empty() {
+        return sentence;
+    }
+    let flags : Vec<String> = flags.iter().map(|&(name,flag)| format!("{}={}",name,cpu.get_flag(flag) as u8)).collect();
+    format!("{}; {}",sentence,flags.join(" "))
+}
+
+fn var_name(table : &SymbolTable, addr : i32) -> String {
+    match table.vars().find(|&(_,a)| a as i32 == addr) {
+        Some((name,_)) => format!(" ({})",name),
+        None => String::new(),
+    }
+}
+
+fn instruction(table : &SymbolTable, addr : u16) -> String {
+    match table.labels().find(|&(_,a,_)| a == addr as usize) {
+        Some((name,_,_)) => format!("instruction {} ({})",addr,name),
+        None => format!("instruction {}",addr),
+    }
+}
+
+fn character(value : i16) -> String {
+    match value as u8 as char {
+        '\n' => format!("a new line ({})",value),
+        c if c.is_ascii_graphic() || c == ' ' => format!("'{}' ({})",c,value),
+        _ => format!("the character {}",value),
+    }
+}
@@ -0,0 +1,215 @@
+//! Operands worked out by the assembler, like `ARR+1`, `END-START` or `#'A'+1`.
+//!
+//! Expressions have `+`, `-`, `*` and `/` (rounding towards zero), brackets and a leading `-`,
+//! with the usual precedence. What each term (a name, number or character) is worth is up to
+//! the caller, so that the same evaluator works for immediates and addresses.
+
+use super::{literal,source};
+
+/// Whether an operand is an expression, that is it has an operator or bracket outside a character
+pub fn is_expression(operand : &str) -> bool {
+    source::unquoted(operand).any(|(_,c)| matches!(c,'+' | '-' | '*' | '/' | '(' | ')'))
+}
+
+#[derive(Clone, Copy)]
+enum Token<'a> {
+    Term(&'a str),
+    Op(char),
+    Open,
+    Close,
+}
+
+fn tokens(expr : &str) -> Result<Vec<Token<'_>>,String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+    while let Some((start,c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '+' | '-' | '*' | '/' => tokens.push(Token::Op(c)),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '\'' => {
+                let end = match literal::quoted_len(&expr[start..]) {
+                    Some(len) => start + len,
+                    None => return Err(format!("Unclosed character in expression ({})",expr)),
+                };
+                while chars.peek().is_some_and(|&(i,_)| i < end) {
+                    chars.next();
+                }
+                tokens.push(Token::Term(&expr[start..end]));
+            },
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i,c)) = chars.peek() {
+                    if c.is_whitespace() || "+-*/()'".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Term(&expr[start..end]));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a,F> {
+    expr : &'a str,
+    tokens : Vec<Token<'a>>,
+    next : usize,
+    term : F,
+}
+impl<'a,F : Fn(&str) -> Result<i32,String>> Parser<'a,F> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.next)
+    }
+    fn overflow(&self) -> String {
+        format!("Expression overflowed ({})",self.expr)
+    }
+    /// Terms added or subtracted
+    fn sum(&mut self) -> Result<i32,String> {
+        let mut value = self.product()?;
+        while let Some(&Token::Op(op @ ('+' | '-'))) = self.peek() {
+            self.next += 1;
+            let rhs = self.product()?;
+            value = if op == '+' {value.checked_add(rhs)} else {value.checked_sub(rhs)}
+                .ok_or_else(|| self.overflow())?;
+        }
+        Ok(value)
+    }
+    /// Factors multiplied or divided
+    fn product(&mut self) -> Result<i32,String> {
+        let mut value = self.factor()?;
+        while let Some(&Token::Op(op @ ('*' | '/'))) = self.peek() {
+            self.next += 1;
+            let rhs = self.factor()?;
+            if op == '/' && rhs == 0 {
+                return Err(format!("Division by zero ({})",self.expr));
+            }
+            value = if op == '*' {value.checked_mul(rhs)} else {value.checked_div(rhs)}
+                .ok_or_else(|| self.overflow())?;
+        }
+        Ok(value)
+    }
+    fn factor(&mut self) -> Result<i32,String> {
+        let token = self.tokens.get(self.next).copied();
+        self.next += 1;
+        match token {
+            Some(Token::Op('-')) => self.factor()?.checked_neg().ok_or_else(|| self.overflow()),
+            Some(Token::Open) => {
+                let value = self.sum()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.next += 1;
+                        Ok(value)
+                    },
+                    _ => Err(format!("Missing ')' in expression ({})",self.expr)),
+                }
+            },
+            Some(Token::Term(term)) => (self.term)(term),
+            _ => Err(format!("Expected a value in expression ({})",self.expr)),
+        }
+    }
+}
+
+/// Works out an expression, asking `term` for the value of each name, number or character in it
+pub fn evaluate(expr : &str, term : impl Fn(&str) -> Result<i32,String>) -> Result<i32,String> {
+    let mut parser = Parser {expr, tokens : tokens(expr)?, next : 0, term};
+    let value = parser.sum()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(Token::Close) => Err(format!("Unmatched ')' in expression ({})",expr)),
+        Some(_) => Err(format!("Expected an operator in expression ({})",expr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers and characters as the assembler reads them, and a couple of names
+    fn term(t : &str) -> Result<i32,String> {
+        match t {
+            "ARR" => Ok(10),
+            "MAX" => Ok(7),
+            _ if t.starts_with('\'') => literal::character(t).map_err(|e| e.to_string()),
+            _ if literal::is_number(t) => literal::number(t,16).map_err(|e| e.to_string()),
+            _ => Err(format!("'{}' is not defined",t)),
+        }
+    }
+    fn eval(expr : &str) -> Result<i32,String> {
+        evaluate(expr,term)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1+2*3"),Ok(7));
+        assert_eq!(eval("10-4-3"),Ok(3));
+        assert_eq!(eval("20/2/5"),Ok(2));
+        assert_eq!(eval("2*3+4*5"),Ok(26));
+        assert_eq!(eval("7/2"),Ok(3));
+        assert_eq!(eval("-7/2"),Ok(-3));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-5"),Ok(-5));
+        assert_eq!(eval("--5"),Ok(5));
+        assert_eq!(eval("3*-2"),Ok(-6));
+        assert_eq!(eval("-(2+3)"),Ok(-5));
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(eval("(1+2)*3"),Ok(9));
+        assert_eq!(eval("((ARR))"),Ok(10));
+        assert_eq!(eval("ARR+(MAX-1)*2"),Ok(22));
+        assert_eq!(eval(" ( 1 + 2 ) * 3 "),Ok(9));
+    }
+
+    #[test]
+    fn unmatched_brackets() {
+        assert_eq!(eval("(1+2"),Err(String::from("Missing ')' in expression ((1+2)")));
+        assert_eq!(eval("1+2)"),Err(String::from("Unmatched ')' in expression (1+2))")));
+        assert!(eval("()").is_err());
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(eval("1/0"),Err(String::from("Division by zero (1/0)")));
+        assert_eq!(eval("ARR/(MAX-7)"),Err(String::from("Division by zero (ARR/(MAX-7))")));
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(eval("65535*65535*65535"),Err(String::from("Expression overflowed (65535*65535*65535)")));
+        assert!(eval("-32768*65535*65535").is_err());
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(eval("'A'+1"),Ok(66));
+        assert_eq!(eval("'a'-'A'"),Ok(32));
+        assert_eq!(eval("'+'+'-'"),Ok(43 + 45));
+        assert_eq!(eval("'\\''*2"),Ok(39 * 2));
+        assert!(eval("'A+1").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(eval("1+").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("*2").is_err());
+        assert_eq!(eval("NOPE+1"),Err(String::from("'NOPE' is not defined")));
+    }
+
+    #[test]
+    fn is_expression_skips_literals() {
+        assert!(is_expression("ARR+1"));
+        assert!(is_expression("#-5"));
+        assert!(!is_expression("'+'"));
+        assert!(!is_expression("ARR"));
+        assert!(is_expression("'+'+1"));
+    }
+}
@@ -0,0 +1,125 @@
+//! The fetch-decode-execute cycle broken down into register transfers.
+use std::fmt::{self,Display,Formatter};
+use super::{AdrMode,Instruction};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Fetch,
+    Decode,
+    Execute,
+}
+impl Display for Phase {
+    fn fmt(&self, f : &mut Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Phase::Fetch   => "FETCH",
+            Phase::Decode  => "DECODE",
+            Phase::Execute => "EXECUTE",
+        })
+    }
+}
+
+/// One step of the cycle, written in register transfer notation
+pub struct Transfer {
+    pub phase : Phase,
+    pub notation : String,
+}
+impl Transfer {
+    pub(crate) fn new(phase : Phase, notation : impl Into<String>) -> Self {
+        Self {phase, notation : notation.into()}
+    }
+}
+impl Display for Transfer {
+    fn fmt(&self, f : &mut Formatter<'_>) -> fmt::Result {
+        write!(f,"{:<8} {}",self.phase,self.notation)
+    }
+}
+
+/// The four transfers that fetch an instruction, in order
+pub const FETCH : [&str;4] = ["MAR ← [PC]","PC ← [PC] + 1","MDR ← [[MAR]]","CIR ← [MDR]"];
+
+/// How the control unit splits up the instruction in the CIR
+pub fn decode(instr : &Instruction) -> String {
+    let mode = match instr {
+        Instruction::LOAD {adr_mode, ..} | Instruction::ADD {adr_mode, ..} | Instruction::SUB {adr_mode, ..}
+        | Instruction::CMP {adr_mode, ..} | Instruction::XOR {adr_mode, ..} | Instruction::OR {adr_mode, ..} => match adr_mode {
+            AdrMode::Immediate => "immediate",
+            AdrMode::Direct    => "direct",
+            AdrMode::Indirect  => "indirect",
+            AdrMode::Indexed   => "indexed",
+        },
+        Instruction::LDR(_) | Instruction::LSL(_) | Instruction::LSR(_) => "immediate",
+        Instruction::INC(_) | Instruction::DEC(_) | Instruction::MOV(_) => "register",
+        Instruction::IO(_) | Instruction::END => return format!("[CIR] decoded: opcode {}, no operand",instr.mnemonic()),
+        _ => "direct",
+    };
+    let operand = instr.to_string();
+    let operand = operand[instr.mnemonic().len()..].trim();
+    format!("[CIR] decoded: opcode {}, operand {} ({} addressing)",instr.mnemonic(),operand,mode)
+}
+
+/// The transfers that execute an instruction, and the address left in the MAR if memory is used.
+/// `ix` is the index register before the instruction runs
+pub fn execute(instr : &Instruction, ix : i16, memory : &[i16]) -> (Vec<String>,Option<u16>) {
+    //reading memory into the MDR
+    let read = |addr : u16| vec![format!("MAR ← {}",addr),String::from("MDR ← [[MAR]]")];
+    let arithmetic = |rhs : u16, adr_mode : AdrMode, op : &str| match adr_mode {
+        AdrMode::Immediate => (vec![format!("ACC ← [ACC] {} {}",op,rhs as i16)],None),
+        _ => {
+            let mut transfers = read(rhs);
+            transfers.push(format!("ACC ← [ACC] {} [MDR]",op));
+            (transfers,Some(rhs))
+        },
+    };
+    match *instr {
+        Instruction::LOAD {data, adr_mode : AdrMode::Immediate} => (vec![format!("ACC ← {}",data as i16)],None),
+        Instruction::LOAD {data, adr_mode : AdrMode::Direct} => {
+            let mut transfers = read(data);
+            transfers.push(String::from("ACC ← [MDR]"));
+            (transfers,Some(data))
+        },
+        Instruction::LOAD {data, adr_mode : AdrMode::Indirect} => {
+            let addr = memory.get(data as usize).copied().unwrap_or(0) as u16;
+            let mut transfers = read(data);
+            transfers.push(String::from("MAR ← [MDR]"));
+            transfers.push(String::from("MDR ← [[MAR]]"));
+            transfers.push(String::from("ACC ← [MDR]"));
+            (transfers,Some(addr))
+        },
+        Instruction::LOAD {data, adr_mode : AdrMode::Indexed} => {
+            let addr = (data as i16).wrapping_add(ix) as u16;
+            (vec![
+                format!("MAR ← {} + [IX]",data),
+                String::from("MDR ← [[MAR]]"),
+                String::from("ACC ← [MDR]"),
+            ],Some(addr))
+        },
+        Instruction::LDR(n) => (vec![format!("IX ← {}",n)],None),
+        Instruction::MOV(r) => (vec![format!("{} ← [ACC]",r)],None),
+        Instruction::STO(addr) => (vec![
+            format!("MAR ← {}",addr),
+            String::from("MDR ← [ACC]"),
+            String::from("[MAR] ← [MDR]"),
+        ],Some(addr)),
+        Instruction::ADD {rhs,adr_mode} => arithmetic(rhs,adr_mode,"+"),
+        Instruction::SUB {rhs,adr_mode} => arithmetic(rhs,adr_mode,"-"),
+        Instruction::XOR {rhs,adr_mode} => arithmetic(rhs,adr_mode,"XOR"),
+        Instruction::OR {rhs,adr_mode}  => arithmetic(rhs,adr_mode,"OR"),
+        Instruction::CMP {rhs,adr_mode} => {
+            let (mut transfers,mar) = arithmetic(rhs,adr_mode,"-");
+            //a comparison only keeps the flags of the subtraction
+            let last = transfers.pop().unwrap();
+            transfers.push(format!("FLAGS ← {}",&last["ACC ← ".len()..]));
+            (transfers,mar)
+        },
+        Instruction::INC(r) => (vec![format!("{} ← [{}] + 1",r,r)],None),
+        Instruction::DEC(r) => (vec![format!("{} ← [{}] - 1",r,r)],None),
+        Instruction::LSL(n) => (vec![format!("ACC ← [ACC] shifted left {}",n)],None),
+        Instruction::LSR(n) => (vec![format!("ACC ← [ACC] shifted right {}",n)],None),
+        Instruction::JMP {addr} => (vec![format!("PC ← {}",addr)],None),
+        Instruction::JPE {addr} => (vec![format!("IF Z = 1 THEN PC ← {}",addr)],None),
+        Instruction::JPN {addr} => (vec![format!("IF Z = 0 THEN PC ← {}",addr)],None),
+        Instruction::IO(true) => (vec![String::from("ACC ← character from the keyboard")],None),
+        Instruction::IO(false) => (vec![String::from("screen ← [ACC]")],None),
+        Instruction::END => (vec![String::from("return control to the operating system")],None),
+    }
+}
@@ -0,0 +1,168 @@
+//! Rewriting a program in one canonical layout.
+//!
+//! Labels, data declarations, constants, macro definitions and ORG are flush left, instructions are
+//! indented with the mnemonic upper cased and the operands lined up, and trailing comments start
+//! in the same column throughout the file. An instruction with a label on the same line keeps it
+//! there, and then every instruction is indented past the longest such label.
+//! Runs of blank lines become one and comments are kept as they were written.
+use super::source::{constant,Line};
+
+const INDENT : usize = 4;
+/// Mnemonics are padded to this so that operands line up
+const MNEMONIC_WIDTH : usize = 3;
+
+pub fn format(src : &str) -> String {
+    let lines : Vec<(bool,Line)> = src.lines()
+        .map(|l| (l.starts_with(|c : char| !c.is_whitespace()),Line::parse(l)))
+        .collect();
+    let indent = lines.iter()
+        .filter(|(_,l)| !l.code.is_empty() && !l.is_data())
+        .filter_map(|(_,l)| l.label.map(|label| label.len() + ": ".len()))
+        .max()
+        .unwrap_or(0)
+        .max(INDENT);
+    let codes : Vec<Option<String>> = lines.iter().map(|(_,l)| format_code(l,indent)).collect();
+    let comment_column = lines.iter().zip(&codes)
+        .filter(|((_,l),_)| l.comment.is_some())
+        .filter_map(|(_,code)| code.as_ref().map(|c| c.len() + 1))
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    let mut blank = false;
+    for ((flush,line),code) in lines.iter().zip(codes) {
+        if line.is_blank() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        match (code,line.comment) {
+            (Some(code),Some(comment)) => {
+                out.push_str(&format!("{:<width$}{}",code,comment,width = comment_column));
+            },
+            (Some(code),None) => out.push_str(&code),
+            (None,Some(comment)) => {
+                //comments on their own line keep to the left if that is where they were
+                let indent = if *flush {0} else {indent};
+                out.push_str(&format!("{:indent$}{}","",comment,indent = indent));
+            },
+            (None,None) => (),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn format_code(line : &Line, indent : usize) -> Option<String> {
+    if let Some(label) = line.label {
+        if line.is_data() {
+            return Some(format!("{}: {}",label,line.code));
+        }
+        if line.code.is_empty() {
+            return Some(format!("{}:",label));
+        }
+        let instruction = format_code(&Line {label : None, ..*line},0)?;
+        return Some(format!("{:<indent$}{}",format!("{}:",label),instruction,indent = indent));
+    }
+    if line.code.is_empty() {
+        return None;
+    }
+    if let Some((name,value)) = constant(line.code) {
+        if line.code.starts_with('.') {
+            return Some(format!(".const {} {}",name,value));
+        }
+        return Some(format!("{} EQU {}",name,value));
+    }
+    let split = line.code.find(|c : char| !c.is_alphabetic()).unwrap_or(line.code.len());
+    let (mnemonic,operand) = line.code.split_at(split);
+    let operand = operand.trim();
+    if ["MACRO","ENDM","ORG"].iter().any(|d| mnemonic.eq_ignore_ascii_case(d)) {
+        //macro definitions are flush left like labels, with their bodies indented
+        return Some(format!("{} {}",mnemonic.to_ascii_uppercase(),operand).trim_end().to_string());
+    }
+    if mnemonic.is_empty() {
+        //not an instruction, leave it for the assembler to complain about
+        return Some(format!("{:indent$}{}","",line.code,indent = indent));
+    }
+    if operand.is_empty() {
+        return Some(format!("{:indent$}{}","",mnemonic.to_ascii_uppercase(),indent = indent));
+    }
+    Some(format!("{:indent$}{:<width$} {}","",mnemonic.to_ascii_uppercase(),operand,indent = indent,width = MNEMONIC_WIDTH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY : &str = "\
+; adds up a list
+start: ldm #0   ; the total
+  sto total
+LOOP:  ldd   list
+\tadd total ; keep going
+
+
+      ; a comment inside the code
+  cmp #','
+  jpe  LOOP
+  out ; prints a ';'
+ end
+MSG: \"a; b, c\"
+LIST: #1,#2 , #3
+max equ 10
+.const MIN 1
+macro swap a, b
+ldd a
+sto b
+endm
+";
+
+    #[test]
+    fn layout() {
+        assert_eq!(format(MESSY),"\
+; adds up a list
+start: LDM #0    ; the total
+       STO total
+LOOP:  LDD list
+       ADD total ; keep going
+
+       ; a comment inside the code
+       CMP #','
+       JPE LOOP
+       OUT       ; prints a ';'
+       END
+MSG: \"a; b, c\"
+LIST: #1,#2 , #3
+max EQU 10
+.const MIN 1
+MACRO swap a, b
+       LDD a
+       STO b
+ENDM
+");
+    }
+
+    #[test]
+    fn idempotent() {
+        let once = format(MESSY);
+        assert_eq!(format(&once),once);
+        let no_labels = "  ldm #5 ;x\n\n\n   out\nend\n";
+        assert_eq!(format(&format(no_labels)),format(no_labels));
+    }
+
+    #[test]
+    fn quoted_separators_are_kept() {
+        //a ';' or ',' in quotes is part of the operand, not a comment or another value
+        assert_eq!(format("  cmp #';'\n"),"    CMP #';'\n");
+        assert_eq!(format("S: \"x;y\" ; z\n"),"S: \"x;y\" ; z\n");
+        assert_eq!(format("  ldm #',' ; a comma\n"),"    LDM #',' ; a comma\n");
+    }
+
+    #[test]
+    fn blank_lines() {
+        assert_eq!(format("\n\n  in\n\n\n\n  out\n\n"),"    IN\n\n    OUT\n");
+    }
+}
@@ -0,0 +1,72 @@
+//! Programs split over several files, with `INCLUDE "lib.rasm"` pulling in the lines of another.
+//!
+//! The file named is found relative to the file the `INCLUDE` is in. A file can't include
+//! itself, directly or through the files it includes.
+use std::{fs,path::{Path,PathBuf}};
+use super::{source::Line,Diagnostic};
+
+/// A line of a program once the files it includes have been pulled in
+#[derive(Clone)]
+pub struct SourceLine {
+    /// The included file it is in, None for the program itself
+    pub file : Option<PathBuf>,
+    /// 0 based
+    pub line : usize,
+    pub text : String,
+}
+
+/// Replaces each `INCLUDE` with the lines of the file it names. `path` is where `src` came from,
+/// if it came from a file, and otherwise included files are found relative to the current directory
+pub fn expand(src : &str, path : Option<&Path>) -> (Vec<SourceLine>,Vec<Diagnostic>) {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut stack : Vec<PathBuf> = path.and_then(|p| fs::canonicalize(p).ok()).into_iter().collect();
+    let dir = path.and_then(Path::parent).unwrap_or_else(|| Path::new(""));
+    include(src,None,dir,&mut stack,&mut lines,&mut errors);
+    (lines,errors)
+}
+
+/// The file named by an `INCLUDE` line, or an error if it is one but doesn't name a file
+fn included(code : &str) -> Option<Result<&str,String>> {
+    let (keyword,rest) = code.split_once(char::is_whitespace).unwrap_or((code,""));
+    if !keyword.eq_ignore_ascii_case("INCLUDE") {
+        return None;
+    }
+    let name = rest.trim().strip_prefix('"').and_then(|r| r.strip_suffix('"')).filter(|n| !n.is_empty());
+    Some(name.ok_or_else(|| String::from("INCLUDE needs a file name in quotes, like INCLUDE \"lib.rasm\"")))
+}
+
+fn include(src : &str, file : Option<&Path>, dir : &Path, stack : &mut Vec<PathBuf>,
+           lines : &mut Vec<SourceLine>, errors : &mut Vec<Diagnostic>) {
+    for (index,text) in src.lines().enumerate() {
+        let error = |message : String| Diagnostic::error(index,message).in_file(file.map(Path::to_path_buf));
+        let name = match included(Line::parse(text).code) {
+            None => {
+                lines.push(SourceLine {file : file.map(Path::to_path_buf), line : index, text : text.to_string()});
+                continue;
+            },
+            Some(Ok(name)) => name,
+            Some(Err(message)) => {
+                errors.push(error(message));
+                continue;
+            },
+        };
+        let path = dir.join(name);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                errors.push(error(format!("Can't include {}: {}",path.display(),e)));
+                continue;
+            },
+        };
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if stack.contains(&canonical) {
+            errors.push(error(format!("{} is already being included, so including it again would never end",path.display())));
+            continue;
+        }
+        stack.push(canonical);
+        let included_dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        include(&String::from_utf8_lossy(&bytes),Some(&path),&included_dir,stack,lines,errors);
+        stack.pop();
+    }
+}
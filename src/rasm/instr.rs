@@ -1,5 +1,7 @@
-use std::cmp::{min,max};
-use super::SymbolTable;
+use std::cmp::max;
+use std::{fmt,ops::Range};
+use std::convert::TryFrom;
+use super::{expr,literal,machine::WORD_BITS,source,SymbolTable};
 #[derive(Clone, Copy)]
 pub enum AdrMode {
     Indexed,
@@ -10,159 +12,386 @@ pub enum AdrMode {
 
 
 
+/// A register named as an operand, which is never a memory address
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+    ACC,
+    IX,
+}
+impl Register {
+    /// `ACC` or `IX`, in any case
+    pub fn parse(name : &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "ACC" => Some(Register::ACC),
+            "IX"  => Some(Register::IX),
+            _     => None,
+        }
+    }
+    /// The operand a register is stored as in machine code
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+    pub fn from_code(code : u16) -> Option<Self> {
+        match code {
+            0 => Some(Register::ACC),
+            1 => Some(Register::IX),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Register::ACC => "ACC",
+            Register::IX  => "IX",
+        })
+    }
+}
+
 //#[derive(Debug)]
+#[derive(Clone)]
 pub enum Instruction {
     IO(bool),
     LOAD {data : u16, adr_mode : AdrMode},
-    LDR(i16), //Load into index register <imm> 
-    MOV     , //ACC -> IX lol 
+    LDR(i16), //Load into index register <imm>
+    MOV(Register), //ACC -> register
     SUB {rhs : u16, adr_mode : AdrMode},
     STO(u16),
     ADD {rhs : u16, adr_mode : AdrMode},
-    INC(bool), //true:ACC, false:IX
-    DEC(bool), //true:ACC, false:IX
-    LSL(u16),  //logical shift left 
+    INC(Register),
+    DEC(Register),
+    LSL(u16),  //logical shift left
     LSR(u16),  //logical shift right
     XOR {rhs : u16, adr_mode : AdrMode},
     OR {rhs : u16, adr_mode : AdrMode},
     CMP {rhs : u16, adr_mode : AdrMode},
-    JPE {addr : u16, adr_mode : AdrMode},
-    JPN {addr : u16, adr_mode : AdrMode},
+    JMP {addr : u16},
+    JPE {addr : u16},
+    JPN {addr : u16},
     END,
-    UNKNOWN,
 
 
 }
 impl Instruction {
-    pub fn new(opcode : String,rhs : u16) -> Self {
-        //handle sto
-        //all addresses have to be offset
-        //TODO:handle acc and ix in a better way?
-        if &opcode == "STO" {
-            return Self::STO(rhs)
-        }
+    pub fn new(opcode : &str,rhs : u16) -> Option<Self> {
         let adr_mode = AdrMode::Direct;
-        match opcode.chars().nth(0).unwrap() {
-            'L' => Instruction::load_instruction(opcode,rhs),
-            'S' => Instruction::SUB {rhs,adr_mode},
-            'A' => Instruction::ADD {rhs,adr_mode},
-            'I' => Instruction::INC(rhs == 0), //Acc is 0 in symtable
-            'D' => Instruction::DEC(rhs == 0),
-            'C' => Instruction::CMP{rhs,adr_mode},
-            'X' => Instruction::XOR{rhs,adr_mode},
-            'O' => Instruction::OR {rhs,adr_mode},
-            'M' => Instruction::MOV,
-            'J' => Instruction::jmp_instruction(opcode,rhs,adr_mode),
-            'E' => Instruction::END,
-             _  => Instruction::UNKNOWN,
+        Some(match opcode {
+            "LDD" => Instruction::LOAD {data : rhs, adr_mode},
+            "LDI" => Instruction::LOAD {data : rhs, adr_mode : AdrMode::Indirect},
+            "LDX" => Instruction::LOAD {data : rhs, adr_mode : AdrMode::Indexed},
+            "STO" => Instruction::STO(rhs),
+            "SUB" => Instruction::SUB {rhs,adr_mode},
+            "ADD" => Instruction::ADD {rhs,adr_mode},
+            "CMP" => Instruction::CMP{rhs,adr_mode},
+            "XOR" => Instruction::XOR{rhs,adr_mode},
+            "OR"  => Instruction::OR {rhs,adr_mode},
+            "JMP" => Instruction::JMP {addr : rhs},
+            "JPE" => Instruction::JPE {addr : rhs},
+            "JPN" => Instruction::JPN {addr : rhs},
+             _    => return None,
 
-        }
+        })
     }
-    pub fn with_imm(opcode : String, rhs : u16) -> Self {
-        let first_char = opcode.chars().nth(0).unwrap();
+    pub fn with_imm(opcode : &str, rhs : u16) -> Option<Self> {
         let adr_mode = AdrMode::Immediate;
-        match first_char {
-            'L' => {
-                if opcode.chars().nth(1).unwrap() == 'S' {
-                    if opcode.chars().nth(2).unwrap() == 'R' {
-                        Instruction::LSR(rhs)
-                    }else {
-                        Instruction::LSL(rhs)
-                    }   
-                }else {
-                    Self::load_instruction(opcode,rhs)
-                }
-            },
-
-            'S' => Instruction::SUB{rhs,adr_mode},
-            'A' => Instruction::ADD{rhs,adr_mode},
-            'C' => Instruction::CMP{rhs,adr_mode},
-            'X' => Instruction::XOR{rhs,adr_mode},
-            'O' => Instruction::OR{rhs,adr_mode},
-            'J' => Self::jmp_instruction(opcode,rhs,adr_mode),
-             _  => Instruction::UNKNOWN,
+        Some(match opcode {
+            "LDM" => Instruction::LOAD {data : rhs, adr_mode},
+            "LDR" => Instruction::LDR(rhs as i16), //Loads into register
+            "LSL" => Instruction::LSL(rhs),
+            "LSR" => Instruction::LSR(rhs),
+            "SUB" => Instruction::SUB{rhs,adr_mode},
+            "ADD" => Instruction::ADD{rhs,adr_mode},
+            "CMP" => Instruction::CMP{rhs,adr_mode},
+            "XOR" => Instruction::XOR{rhs,adr_mode},
+            "OR"  => Instruction::OR{rhs,adr_mode},
+             _    => return None,
 
+        })
+    }
+    pub fn with_register(opcode : &str, register : Register) -> Option<Self> {
+        Some(match opcode {
+            "INC" => Instruction::INC(register),
+            "DEC" => Instruction::DEC(register),
+            "MOV" => Instruction::MOV(register),
+             _    => return None,
+        })
+    }
+    pub fn mnemonic(&self) -> &'static str {
+        use AdrMode::*;
+        match *self {
+            Instruction::IO(true)  => "IN",
+            Instruction::IO(false) => "OUT",
+            Instruction::LOAD {adr_mode : Immediate, ..} => "LDM",
+            Instruction::LOAD {adr_mode : Direct, ..}    => "LDD",
+            Instruction::LOAD {adr_mode : Indirect, ..}  => "LDI",
+            Instruction::LOAD {adr_mode : Indexed, ..}   => "LDX",
+            Instruction::LDR(_)    => "LDR",
+            Instruction::MOV(_)    => "MOV",
+            Instruction::SUB {..}  => "SUB",
+            Instruction::STO(_)    => "STO",
+            Instruction::ADD {..}  => "ADD",
+            Instruction::INC(_)    => "INC",
+            Instruction::DEC(_)    => "DEC",
+            Instruction::LSL(_)    => "LSL",
+            Instruction::LSR(_)    => "LSR",
+            Instruction::XOR {..}  => "XOR",
+            Instruction::OR {..}   => "OR",
+            Instruction::CMP {..}  => "CMP",
+            Instruction::JMP {..}  => "JMP",
+            Instruction::JPE {..}  => "JPE",
+            Instruction::JPN {..}  => "JPN",
+            Instruction::END       => "END",
         }
     }
-    fn load_instruction(opcode : String,data : u16) -> Self {
-        match opcode.chars().last().expect("Error on load instruction") {
-            'D' => Instruction::LOAD{data,adr_mode : AdrMode::Direct},
-            'M' => Instruction::LOAD{data,adr_mode : AdrMode::Immediate},
-            'R' => Instruction::LDR(data as i16), //Loads into register
-            'I' => Instruction::LOAD{data, adr_mode : AdrMode::Indirect},
-            'X' => Instruction::LOAD{data, adr_mode : AdrMode::Indexed},
-             _  => Instruction::UNKNOWN,
+    /// The operand as it is stored, and whether it is an immediate value rather than an address
+    pub fn operand(&self) -> Option<(u16,bool)> {
+        match *self {
+            Instruction::LOAD {data : v, adr_mode} | Instruction::SUB {rhs : v, adr_mode}
+            | Instruction::ADD {rhs : v, adr_mode} | Instruction::XOR {rhs : v, adr_mode}
+            | Instruction::OR {rhs : v, adr_mode}  | Instruction::CMP {rhs : v, adr_mode} => {
+                Some((v,matches!(adr_mode,AdrMode::Immediate)))
+            },
+            Instruction::LDR(v) => Some((v as u16,true)),
+            Instruction::LSL(v) | Instruction::LSR(v) => Some((v,true)),
+            Instruction::STO(v) | Instruction::JMP {addr : v}
+            | Instruction::JPE {addr : v} | Instruction::JPN {addr : v} => Some((v,false)),
+            Instruction::INC(r) | Instruction::DEC(r) | Instruction::MOV(r) => Some((r.code(),false)),
+            Instruction::IO(_) | Instruction::END => None,
         }
     }
-    fn jmp_instruction(opcode : String,addr : u16,adr_mode : AdrMode) -> Self {
-    
-        match opcode.chars().nth(2).expect("Error on Jump instruction") {
-            'E' => Instruction::JPE {addr,adr_mode},
-            'N' => Instruction::JPN {addr,adr_mode},
-            
-             _  => Instruction::UNKNOWN,
+
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::INC(r) | Instruction::DEC(r) | Instruction::MOV(r) => write!(f,"{} {}",self.mnemonic(),r),
+            _ => match self.operand() {
+                Some((v,true))  => write!(f,"{} #{}",self.mnemonic(),v as i16),
+                Some((v,false)) => write!(f,"{} {}",self.mnemonic(),v),
+                None            => write!(f,"{}",self.mnemonic()),
+            },
         }
     }
-    
 }
-pub fn str_to_instr(table : &mut SymbolTable,line : &str) -> Instruction {
-    if line == "END" {
-         return Instruction::END
-      
+/// Why a line can't be assembled, and which part of its operand is wrong when that is known
+#[derive(Debug)]
+pub struct LineError {
+    pub message : String,
+    /// A byte range into the operand, the values of a data declaration or the value of a constant
+    pub span : Option<Range<usize>>,
+}
+impl LineError {
+    /// For an error in part of the operand that starts `by` bytes into it
+    fn shift(self, by : usize) -> Self {
+        Self {span : self.span.map(|s| s.start + by..s.end + by), ..self}
     }
-
-    //code.push(line.chars().skip_while(|c| !c.is_alphabetic()).collect());
-    //tabxxxspace---
-    else if line.ends_with("IN") || line.ends_with("OUT") {
-         return Instruction::IO(line.ends_with("IN"))
-        
+}
+impl From<String> for LineError {
+    fn from(message : String) -> Self {
+        Self {message, span : None}
     }
+}
+impl fmt::Display for LineError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
 
-
+pub fn str_to_instr(table : &mut SymbolTable,line : &str) -> Result<Instruction,LineError> {
     let opcode = line.chars()
             .take_while(|c| c.is_alphabetic())
-            .collect::<String>();
-    let n = opcode.len(); // presumably 3
-    
-    let ident = line.split_at(n + 1).1.trim();
-    //immediate addresses
-    if !ident.starts_with("#") {
-    
-        let p = ident.parse::<i16>();
-        if p.is_err() {
-            table.add_var(ident.to_string());
-        }else{
-            let p = p.unwrap();
-            table.min_addr = min(table.min_addr, p as u16);
-            table.max_addr = max(table.max_addr, p as u16);
-            return Instruction::with_imm(opcode,p as u16)
+            .collect::<String>()
+            .to_ascii_uppercase();
+    let ident = line[opcode.len()..].trim();
+    match opcode.as_str() {
+        "END" | "IN" | "OUT" if !ident.is_empty() => {
+            return Err(format!("'{}' does not take an operand", opcode).into())
+        },
+        "END" => return Ok(Instruction::END),
+        "IN" | "OUT" => return Ok(Instruction::IO(opcode == "IN")),
+        _ => (),
+    }
+    if ident.is_empty() {
+        return Err(format!("'{}' expects an operand", line).into());
+    }
+    //registers are kept apart from memory, so they are only ever the operand of these
+    let register = Register::parse(ident);
+    match (opcode.as_str(),register) {
+        ("MOV",Some(Register::ACC)) => return Err(format!("MOV copies ACC into another register, so can't copy it into ACC ({})",line).into()),
+        ("INC" | "DEC" | "MOV",Some(register)) => return Ok(Instruction::with_register(&opcode,register).unwrap()),
+        ("INC" | "DEC" | "MOV",None) => return Err(format!("'{}' needs a register, ACC or IX, as its operand ({})",opcode,line).into()),
+        (_,Some(register)) => return Err(format!("{} is a register, not a memory address or value, so {} can't use it ({})",register,opcode,line).into()),
+        _ => (),
+    }
+    let decoded = if expr::is_expression(ident) {
+        //a '#' or character makes an immediate value, anything else works out an address
+        let (imm,text) = match ident.strip_prefix('#') {
+            Some(text) => (true,text),
+            None       => (ident.starts_with('\''),ident),
+        };
+        let value = expr::evaluate(text,|t| term(table,t,imm))?;
+        if imm {
+            Instruction::with_imm(&opcode, word(ident,value)? as u16)
+        } else {
+            let addr = u16::try_from(value).ok().filter(|&a| a <= i16::MAX as u16)
+                .ok_or_else(|| format!("{} works out to {}, which is not an address",ident,value))?;
+            Instruction::new(&opcode,addr)
+        }
+    } else if ident.starts_with('#') {
+        Instruction::with_imm(&opcode, immediate(table,ident)? as u16)
+    } else if ident.starts_with('\'') {
+        Instruction::with_imm(&opcode, literal_value(ident,ident)? as u16)
+    } else if ident.starts_with(|c : char| c.is_ascii_digit() || c == '&') {
+        //address literals, not B101 which is a name here
+        let addr = u16::try_from(literal_value(ident,ident)?).ok().filter(|&a| a <= i16::MAX as u16)
+            .ok_or_else(|| format!("{} is not an address, which goes from 0 to {}",ident,i16::MAX))?;
+        table.max_addr = max(table.max_addr, addr);
+        Instruction::new(&opcode,addr)
+    } else {
+        let addr = match table.lookup(ident) {
+            Some(addr) => addr,
+            None => table.add_var(ident.to_string()) as u16,
+        };
+        Instruction::new(&opcode,addr)
+    };
+    Ok(decoded.ok_or_else(|| format!("Unknown instruction or addressing mode ({})", line))?)
+}
+
+/// An immediate value such as `#5`, `#-5`, `#B101`, `#&FF`, `#'A'`, a constant, `#MAX`, or the
+/// address of a label or variable, `#ARR`
+fn immediate(table : &SymbolTable, ident : &str) -> Result<i32,LineError> {
+    immediate_value(table,&ident[1..],ident).map_err(|e| e.shift(1))
+}
 
+/// What an immediate value is worth without its `#`, with the immediate given for errors
+fn immediate_value(table : &SymbolTable, fstring : &str, ident : &str) -> Result<i32,LineError> {
+    match fstring.chars().next() {
+        Some('\'') => literal_value(fstring,ident),
+        _ if literal::is_number(fstring) => literal_value(fstring,ident),
+        _ if source::is_name(fstring) => named(table,fstring,true)
+            .ok_or_else(|| format!("'{}' is not defined ({})",fstring,ident).into()),
+        _ => Err(format!("Incorrectly formated immediate ({})", ident).into()),
+    }
+}
+
+/// A number or character, with the operand it is in given for errors. The span is into `text`
+fn literal_value(text : &str, ident : &str) -> Result<i32,LineError> {
+    let value = if text.starts_with('\'') {literal::character(text)} else {literal::number(text,WORD_BITS)};
+    value.map_err(|e| LineError {message : format!("{} ({})",e,ident), span : Some(e.span)})
+}
+
+/// Checks a worked out value fits in a word
+fn word(ident : &str, value : i32) -> Result<i16,String> {
+    if literal::fits(value as i64,WORD_BITS) {
+        Ok(value as i16)
+    } else {
+        Err(format!("{} works out to {}, which does not fit in a {} bit word",ident,value,WORD_BITS))
+    }
+}
+
+/// The value of a constant definition, a number (with or without `#`), a character or an expression
+pub fn str_to_const(table : &SymbolTable, value : &str) -> Result<i16,LineError> {
+    if expr::is_expression(value) {
+        let text = value.strip_prefix('#').unwrap_or(value);
+        Ok(word(value,expr::evaluate(text,|t| term(table,t,true))?)?)
+    } else if value.starts_with('#') {
+        immediate(table,value).map(|v| v as i16)
+    } else {
+        immediate_value(table,value,&format!("#{}",value)).map(|v| v as i16)
+    }
+}
+
+/// What a name is worth, the value of a constant or the address of a label or variable. Constants
+/// are looked up first in an immediate value and last in an address
+fn named(table : &SymbolTable, name : &str, imm : bool) -> Option<i32> {
+    let constant = table.constant(name).map(i32::from);
+    let address = table.lookup(name).map(i32::from);
+    if imm {constant.or(address)} else {address.or(constant)}
+}
+
+/// What a term of an expression is worth
+fn term(table : &SymbolTable, t : &str, imm : bool) -> Result<i32,String> {
+    if let Some(register) = Register::parse(t) {
+        return Err(format!("{} is a register, so can't be used in an expression",register));
+    }
+    if source::is_name(t) && !literal::is_number(t) {
+        return named(table,t,imm).ok_or_else(|| format!("'{}' is not defined",t));
+    }
+    literal_value(t,t).map_err(|e| e.message)
+}
+
+/// How many memory cells a data declaration such as `#1,#2,'A'` or `"HELLO"` takes, which can be
+/// known before the constants its values use
+pub fn data_len(data : &str) -> Result<usize,LineError> {
+    let mut len = 0;
+    for item in source::comma_separated(data) {
+        if item.starts_with('"') {
+            len += string(item).map_err(|e| e.shift(offset(data,item)))?.len();
+        } else if item.is_empty() {
+            return Err(format!("Missing a value in data ({})", data).into());
+        } else {
+            len += 1;
         }
     }
-    //dealing with immediate values
-    if ident.starts_with("#") {
-        let fstring = &ident[1..];
-        let imm  = match fstring.chars().nth(0).unwrap() {
-            'B'       => i16::from_str_radix(&fstring[2..],2),
-            '&'       => i16::from_str_radix(&fstring[2..], 16),
-            '0'..='9' => i16::from_str_radix(fstring, 10),
-            _         => {
-                            eprintln!("Incorrectly formated immediate ({})", line);
-                            std::process::exit(-1);
-            }        
-        };
-        let imm = imm.expect("Error while parsing immediate value");
-        return Instruction::with_imm(opcode, imm as u16)
+    Ok(len)
+}
+
+/// The initial values of a data declaration such as `#1,#2,'A'` or `"HELLO"`, one per memory cell
+pub fn str_to_data(table : &SymbolTable, data : &str) -> Result<Vec<i16>,LineError> {
+    let mut values = Vec::new();
+    for item in source::comma_separated(data) {
+        let at = offset(data,item);
+        if item.starts_with('#') && expr::is_expression(item) {
+            values.push(str_to_const(table,item).map_err(|e| e.shift(at))?);
+        } else if item.starts_with('#') {
+            values.push(immediate(table,item).map_err(|e| e.shift(at))? as i16);
+        } else if item.starts_with('\'') {
+            values.push(literal_value(item,item).map_err(|e| e.shift(at))? as i16);
+        } else if item.starts_with('"') {
+            let string = string(item).map_err(|e| e.shift(at))?;
+            values.extend(string.into_iter().map(|c| c as i16));
+        } else if item.is_empty() {
+            return Err(format!("Missing a value in data ({})", data).into());
+        } else {
+            return Err(format!("Data values must be immediates, characters or strings ({})", item).into());
+        }
+    }
+    Ok(values)
+}
+
+/// The characters of a string literal. The span is into `item`
+fn string(item : &str) -> Result<Vec<i32>,LineError> {
+    literal::string(item).map_err(|e| LineError {message : format!("{} ({})",e,item), span : Some(e.span)})
+}
+
+/// Where `part`, which is sliced out of `text`, starts in it
+fn offset(text : &str, part : &str) -> usize {
+    part.as_ptr() as usize - text.as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    /// The value `LDM` is given by `operand`, in a program with a label, a variable and a constant
+    fn loaded(operand : &str) -> Result<u16,String> {
+        let src = format!("START: LDM {}\nEND\nX: #7\nMAX EQU 9",operand);
+        let program = crate::assemble(&src).map_err(|e| e.to_string())?;
+        Ok(program.code[0].operand().unwrap().0)
     }
-    if ident.starts_with("'") {
-        let start = ident.find("'").unwrap();
-        //This should be a character constant so len == 1
-        let end = ident.rfind("'").unwrap();
-        assert_eq!(end - start,2);
-        let c = ident[start+1..end].chars().nth(0).unwrap();
-        return Instruction::with_imm(opcode, c as u8 as u16);
+
+    #[test]
+    fn names_in_an_immediate() {
+        let x = loaded("#X").unwrap();
+        assert_eq!(loaded("#X+0"),Ok(x));
+        assert_eq!(loaded("#X+1"),Ok(x + 1));
+        assert_eq!(loaded("#START"),Ok(0));
+        assert_eq!(loaded("#START+0"),Ok(0));
+        assert_eq!(loaded("#MAX"),Ok(9));
+        assert_eq!(loaded("#MAX+0"),Ok(9));
     }
-    Instruction::new(opcode,table.get(ident.to_string()))
-    
 
+    #[test]
+    fn undefined_name_in_an_immediate() {
+        assert!(loaded("#NOPE").unwrap_err().contains("'NOPE' is not defined"));
+        assert!(loaded("#NOPE+0").unwrap_err().contains("'NOPE' is not defined"));
+    }
 }
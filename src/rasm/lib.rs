@@ -0,0 +1,688 @@
+//! An assembler and interpreter for the 'reduced' assembly language taught for AS level.
+//!
+//! ```
+//! let program = rasm::assemble("LDM #5\nADD #3\nEND").unwrap();
+//! let mut machine = rasm::Machine::new(program, rasm::Config::default());
+//! machine.run().unwrap();
+//! assert_eq!(machine.acc(), 8);
+//! ```
+#![allow(clippy::upper_case_acronyms)]
+use std::path::{Path,PathBuf};
+use std::collections::{BTreeSet,HashMap,VecDeque,hash_map::Entry};
+use std::{fmt,ops::Range};
+mod machine;
+mod cpu;
+mod computer;
+mod mem;
+mod instr;
+mod expr;
+mod literal;
+mod macros;
+mod include;
+mod spec;
+mod object;
+mod source;
+mod format;
+mod cfg;
+mod lint;
+mod listing;
+mod docs;
+mod explain;
+mod fde;
+pub use instr::{Instruction,AdrMode,Register};
+pub use machine::{Machine,Config,DEFAULT_MAX_STEPS,MEMORY_SIZE};
+pub use cpu::{Fault,FLAGS};
+pub use mem::Memory;
+pub use fde::{Transfer,Phase};
+pub use lint::lint;
+
+/// The parts of the assembler and machine that `rasm-cli` builds its other commands on. These
+/// are not part of the stable API and can change in any release
+#[doc(hidden)]
+pub mod tooling {
+    pub use crate::cfg::to_dot;
+    pub use crate::computer::{Computer,ComputerBuilder};
+    pub use crate::cpu::{CPU,Word};
+    pub use crate::format::format;
+    pub use crate::instr::{str_to_const,str_to_data,str_to_instr};
+    pub use crate::listing::listing;
+    pub use crate::mem::View;
+    pub use crate::spec::{Spec,TestCase,Outcome,run_case};
+    pub mod object {
+        pub use crate::object::{encode,read,write,Object};
+    }
+    pub mod source {
+        pub use crate::source::{constant,identifiers,is_name,org,references,spans,Line,Spans};
+    }
+    pub mod docs {
+        pub use crate::docs::{addressing_mode,mnemonic,MNEMONICS};
+    }
+}
+use instr::{str_to_instr,str_to_data,str_to_const,data_len,LineError};
+
+#[derive(Clone, Copy)]
+pub enum DisplayStyle {
+    Denary,
+    Binary,
+    Hex,
+    /// Signed and unsigned denary, binary, hex and the ASCII character side by side
+    All,
+}
+impl DisplayStyle {
+    /// Writes a 16 bit word in this style
+    pub fn format(self, value : i16) -> String {
+        match self {
+            DisplayStyle::Denary => value.to_string(),
+            DisplayStyle::Binary => format!("{:016b}",value as u16),
+            DisplayStyle::Hex    => format!("{:04X}",value as u16),
+            DisplayStyle::All    => {
+                let c = match value {
+                    0x20..=0x7E => format!("'{}'",value as u8 as char),
+                    _           => String::from("-"),
+                };
+                format!("{:>6} {:>8} {:016b} {:04X} {:>4}",value,value as u16,value as u16,value as u16,c)
+            },
+        }
+    }
+    /// What each column of [`DisplayStyle::All`] is, lined up with what it writes
+    pub const ALL_COLUMNS : &'static str = "SIGNED UNSIGNED BINARY           HEX  CHAR";
+}
+
+/// How a variable asks to be shown, with a `; @display NAME format` comment
+#[derive(Clone, Copy)]
+pub enum VarFormat {
+    Style(DisplayStyle),
+    /// The ASCII character, for variables that hold text
+    Char,
+}
+impl VarFormat {
+    pub fn parse(name : &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "dec" | "denary" => VarFormat::Style(DisplayStyle::Denary),
+            "bin" | "binary" => VarFormat::Style(DisplayStyle::Binary),
+            "hex"            => VarFormat::Style(DisplayStyle::Hex),
+            "all"            => VarFormat::Style(DisplayStyle::All),
+            "char"           => VarFormat::Char,
+            _                => return None,
+        })
+    }
+    pub fn format(self, value : i16) -> String {
+        match self {
+            VarFormat::Style(style) => style.format(value),
+            VarFormat::Char => match value {
+                0x20..=0x7E | 0x09 | 0x0A | 0x0D => format!("'{}'",(value as u8 as char).escape_default()),
+                _ => value.to_string(),
+            },
+        }
+    }
+    /// The most characters [`VarFormat::format`] writes, for lining up columns
+    pub fn width(self) -> usize {
+        [i16::MIN,0x0A,0x41].iter().map(|&v| self.format(v).len()).max().unwrap()
+    }
+}
+
+#[derive(Clone)]
+pub struct SymbolTable {
+    table : HashMap<String,usize>,
+    /// label -> (address, line it is defined on)
+    label_table : HashMap<String,(usize,usize)>,
+    pub labels : usize,
+    max_addr : u16,
+    /// One past the highest address given to a variable
+    pub num_vars : usize,
+    /// Every address given to a variable, including each cell of a declared one
+    taken : BTreeSet<usize>,
+    /// Formats asked for with `@display`
+    formats : HashMap<String,VarFormat>,
+    /// Names given to numbers with `EQU`, kept apart from labels and variables
+    constants : HashMap<String,i16>,
+}
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            table : HashMap::new(),
+            label_table : HashMap::new(),
+            labels : 0,
+            max_addr : 0,
+            num_vars : 0,
+            taken : BTreeSet::new(),
+            formats : HashMap::new(),
+            constants : HashMap::new(),
+        }
+    }
+    /// The address of a variable, giving it one first if it doesn't have one yet
+    pub fn add_var(&mut self,ident : String) -> usize {
+        //the first address not already given to a variable, there can be gaps left by ORG
+        let l = (0..).find(|a| !self.taken.contains(a)).unwrap();
+        match self.table.entry(ident) {
+            Entry::Occupied(e) => *e.get(),
+            Entry::Vacant(e) => {
+                e.insert(l);
+                self.taken.insert(l);
+                self.num_vars = self.num_vars.max(l + 1);
+                l
+            },
+        }
+    }
+    /// Reserves `len` consecutive cells for a declared variable after every other variable,
+    /// returning the first address
+    pub fn add_data(&mut self, ident : String, len : usize) -> Result<usize,String> {
+        self.add_data_at(ident,self.num_vars,len)
+    }
+    /// Reserves `len` consecutive cells for a declared variable starting at `addr`
+    pub fn add_data_at(&mut self, ident : String, addr : usize, len : usize) -> Result<usize,String> {
+        if let Some(register) = Register::parse(&ident) {
+            return Err(format!("{} is a register so can't be the name of a variable",register));
+        }
+        if !source::is_name(&ident) {
+            return Err(format!("'{}' can't be the name of a variable, use letters, digits and '_'",ident));
+        }
+        if self.table.contains_key(&ident) || self.is_label(&ident) {
+            return Err(format!("'{}' is defined more than once",ident));
+        }
+        if let Some(a) = (addr..addr + len).find(|a| self.taken.contains(a)) {
+            return Err(format!("'{}' would overlap another variable at address {}",ident,a));
+        }
+        self.table.insert(ident,addr);
+        self.taken.extend(addr..addr + len);
+        self.num_vars = self.num_vars.max(addr + len);
+        Ok(addr)
+    }
+    pub fn add_label(&mut self, k : String, v : usize, line : usize) {
+        self.label_table.insert(k, (v,line));
+        self.labels += 1;
+    }
+    pub fn add_const(&mut self, name : String, value : i16) -> Result<(),String> {
+        if !source::is_name(&name) || Register::parse(&name).is_some() {
+            return Err(format!("'{}' can not be the name of a constant",name));
+        }
+        match self.constants.entry(name) {
+            Entry::Occupied(e) => Err(format!("The constant '{}' is defined more than once",e.key())),
+            Entry::Vacant(e) => {
+                e.insert(value);
+                Ok(())
+            },
+        }
+    }
+    /// The value of a constant, which is only used after a `#`
+    pub fn constant(&self, name : &str) -> Option<i16> {
+        self.constants.get(name).copied()
+    }
+    /// Every constant with its value
+    pub fn constants(&self) -> impl Iterator<Item = (&str,i16)> {
+        self.constants.iter().map(|(k,&v)| (k.as_str(),v))
+    }
+    pub fn is_label(&self, key : &str) -> bool {
+        self.label_table.contains_key(key)
+    }
+    pub fn lookup(&self, key : &str) -> Option<u16> {
+        self.label_table.get(key).map(|&(v,_)| v)
+            .or_else(|| self.table.get(key).copied())
+            .map(|v| v as u16)
+    }
+    /// Every label with its address and the line it is defined on
+    pub fn labels(&self) -> impl Iterator<Item = (&str,usize,usize)> {
+        self.label_table.iter().map(|(k,&(v,line))| (k.as_str(),v,line))
+    }
+    /// Every variable with its address
+    pub fn vars(&self) -> impl Iterator<Item = (&str,usize)> {
+        self.table.iter().map(|(k,&v)| (k.as_str(),v))
+    }
+    pub fn set_format(&mut self, var : String, format : VarFormat) {
+        self.formats.insert(var,format);
+    }
+    /// How a variable asked to be shown, if it did
+    pub fn format(&self, var : &str) -> Option<VarFormat> {
+        self.formats.get(var).copied()
+    }
+    /// Writes a variable's value in its own format, or in `style` if it has none
+    pub fn format_value(&self, var : &str, value : i16, style : DisplayStyle) -> String {
+        self.format(var).unwrap_or(VarFormat::Style(style)).format(value)
+    }
+    /// The addresses a program uses, its variables and any address given as a number, in order
+    pub fn addresses(&self) -> Vec<usize> {
+        let literals = 0..=self.max_addr as usize;
+        literals.chain(self.taken.iter().copied()).collect::<BTreeSet<_>>().into_iter().collect()
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The program can not be assembled
+    Error,
+    /// The program assembles but probably doesn't do what was meant, see [`lint`]
+    Warning,
+}
+
+/// A problem found in a program, with the (0 based) source line it came from
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub line : usize,
+    /// The included file the line is in, None for the program itself
+    pub file : Option<PathBuf>,
+    pub severity : Severity,
+    pub message : String,
+    /// The part of the line that is wrong, as a byte range into it, when that is known
+    pub columns : Option<Range<usize>>,
+}
+impl Diagnostic {
+    pub fn error(line : usize, message : String) -> Self {
+        Self {line, file : None, severity : Severity::Error, message, columns : None}
+    }
+    pub fn warning(line : usize, message : String) -> Self {
+        Self {line, file : None, severity : Severity::Warning, message, columns : None}
+    }
+    pub fn in_file(self, file : Option<PathBuf>) -> Self {
+        Self {file, ..self}
+    }
+    pub fn at(self, columns : Option<Range<usize>>) -> Self {
+        Self {columns, ..self}
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"{}",location(self.file.as_deref(),self.line))?;
+        match (&self.columns,&self.file) {
+            (Some(columns),Some(_)) => write!(f,":{}",columns.start + 1)?,
+            (Some(columns),None)    => write!(f,", column {}",columns.start + 1)?,
+            (None,_)                => (),
+        }
+        match self.severity {
+            Severity::Error   => write!(f,": {}",self.message),
+            Severity::Warning => write!(f,": warning: {}",self.message),
+        }
+    }
+}
+
+/// Where a line is, `line 12` in the program itself or `lib.rasm:12` in an included file
+fn location(file : Option<&Path>, line : usize) -> String {
+    match file {
+        Some(file) => format!("{}:{}",file.display(),line + 1),
+        None       => format!("line {}",line + 1),
+    }
+}
+
+/// Every problem that stopped a program from assembling, in source order
+#[derive(Clone, Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+impl Diagnostics {
+    pub fn iter(&self) -> std::slice::Iter<'_,Diagnostic> {
+        self.0.iter()
+    }
+}
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i,d) in self.0.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f,"{}",d)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// An assembled program
+pub type Program = Code;
+
+/// Where an instruction came from
+#[derive(Clone, Debug)]
+pub struct DebugInfo {
+    /// The (0 based) source line, which is the call for an instruction from a macro
+    pub line : usize,
+    /// The included file the line is in, None for the program itself
+    pub file : Option<PathBuf>,
+    /// The instruction as it was written
+    pub text : String,
+}
+impl DebugInfo {
+    /// `line 12`, or `lib.rasm:12` for an instruction in an included file
+    pub fn location(&self) -> String {
+        location(self.file.as_deref(),self.line)
+    }
+}
+
+#[derive(Clone)]
+pub struct Code{
+    table : SymbolTable,
+    pub code : Vec<Instruction>,
+    pub debug_info : Vec<DebugInfo>,
+    /// The macro call each instruction was expanded from, if it was
+    calls : Vec<Option<String>>,
+    /// Whether each instruction is the first its source line made, a macro call making several
+    firsts : Vec<bool>,
+    /// The addresses `ORG` skipped over, which hold an END so that `code` has no holes
+    gaps : Vec<bool>,
+    /// The initial value of every memory cell given one by a data declaration, by address
+    pub data : Vec<(u16,i16)>,
+    /// The lines the program was assembled from once files were included, for its listing
+    source : Vec<include::SourceLine>,
+}
+impl Code {
+    pub fn new(table : SymbolTable,code : Vec<Instruction>,debug_info : Vec<DebugInfo>,data : Vec<(u16,i16)>) -> Self {
+        let calls = vec![None;code.len()];
+        let firsts = vec![true;code.len()];
+        let gaps = vec![false;code.len()];
+        Self {table,code,debug_info,calls,firsts,gaps,data,source : Vec::new()}
+    }
+    /// The instruction at address `i`, None past the end or where `ORG` left no instruction
+    pub fn get(&self,i : usize) -> Option<(&Instruction,&DebugInfo)>
+    {
+        if self.is_gap(i) {
+            return None;
+        }
+        self.code.get(i).map(|v| (v,&self.debug_info[i]))
+    }
+    /// Whether `ORG` skipped over address `i`
+    pub fn is_gap(&self, i : usize) -> bool {
+        self.gaps.get(i).copied().unwrap_or(false)
+    }
+    /// The address of the first instruction, where the program starts running
+    pub fn start(&self) -> usize {
+        self.gaps.iter().position(|&gap| !gap).unwrap_or(0)
+    }
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.table
+    }
+    /// The line that called the macro instruction `i` came from
+    pub fn macro_call(&self, i : usize) -> Option<&str> {
+        self.calls.get(i)?.as_deref()
+    }
+    /// Whether instruction `i` is the first its source line made, where a breakpoint on the line goes
+    pub fn starts_line(&self, i : usize) -> bool {
+        !self.is_gap(i) && self.firsts.get(i).copied().unwrap_or(false)
+    }
+}
+
+/// Assembles the source of a program, finding any files it includes relative to the current directory
+pub fn assemble(src : &str) -> Result<Program,Diagnostics> {
+    parse_code(src,None)
+}
+
+/// Assembles the source of a program that was read from `file`, which is where the files it
+/// includes are found relative to
+pub fn assemble_from(src : &str, file : &Path) -> Result<Program,Diagnostics> {
+    parse_code(src,Some(file))
+}
+
+/// Reads and assembles the program in `file`
+pub fn build_code(file : &Path) -> Result<Code,Diagnostics> {
+    let raw_bytes = std::fs::read(file)
+        .map_err(|e| Diagnostics(vec![Diagnostic::error(0,e.to_string())]))?;
+    assemble_from(&String::from_utf8_lossy(&raw_bytes),file)
+}
+
+fn parse_code(file_contents : &str, path : Option<&Path>) -> Result<Code,Diagnostics> {
+    //problems are found against the lines once files are included and macros expanded,
+    //`errors` is indexed by those and `diagnostics` by the lines they came from
+    let mut errors = Vec::new();
+    let mut table = SymbolTable::new();
+    let mut lines = Vec::new();
+    let mut formats = Vec::new();
+    let mut constants = Vec::new();
+    let mut declarations = Vec::new();
+    let mut data = Vec::new();
+    //where the next instruction goes, and where the next declared variable goes if `ORG` said
+    let mut code_addr = 0;
+    let mut data_org = None;
+    let (included,mut diagnostics) = include::expand(file_contents,path);
+    let (expanded,macro_errors) = macros::expand(included.clone());
+    diagnostics.extend(macro_errors);
+    for (index,macros::Expanded {text,call,..}) in expanded.iter().enumerate() {
+       //LABELS:
+       //   Labels need to be added to the table with the address of the next instruction
+       //Variables:
+       //   address aliases need to be added to table with offset to bottom of code
+       //Address literals:
+       //   Address literals start with # and are left as normal
+       //Comments:
+       //   Comments should start with ';' and are removed from the code
+
+        let line = source::Line::parse(text);
+        let annotation = line.comment.and_then(|c| c[1..].trim().strip_prefix("@display"))
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+        if let Some(annotation) = annotation {
+            match annotation.split_whitespace().collect::<Vec<_>>()[..] {
+                [name,format] => match VarFormat::parse(format) {
+                    Some(format) => formats.push((index,name,format)),
+                    None => errors.push(Diagnostic::error(index,
+                        format!("Unknown display format '{}', use dec, bin, hex, char or all",format))),
+                },
+                _ => errors.push(Diagnostic::error(index,String::from("@display expects a variable and a format, like '@display MASK bin'"))),
+            }
+        }
+        if let Some(name) = line.label.filter(|_| line.is_data()) {
+            declarations.push((index,name,line.code,data_org.take()));
+            continue;
+        }
+        if line.label.is_some() && (source::org(line.code).is_some() || source::constant(line.code).is_some()) {
+            errors.push(Diagnostic::error(index,String::from("A label can't go on the same line as ORG, EQU or .const")));
+            continue;
+        }
+        if let Some(operand) = source::org(line.code) {
+            match literal::number(operand,machine::WORD_BITS).ok().filter(|&a| (0..=i16::MAX as i32).contains(&a)) {
+                Some(addr) => {
+                    code_addr = addr as usize;
+                    data_org = Some(addr as usize);
+                },
+                None => errors.push(Diagnostic::error(index,String::from("ORG needs an address, like 'ORG 40'"))),
+            }
+            continue;
+        }
+        if let Some((name,value)) = source::constant(line.code) {
+            constants.push((index,name,value));
+            continue;
+        }
+        if let Some(label) = line.label {
+            if !source::is_name(label) || Register::parse(label).is_some() {
+                errors.push(Diagnostic::error(index,format!("'{}' can't be the name of a label, use letters, digits and '_'",label)));
+                continue;
+            }
+            //labels are collected up front so that they can be jumped to before they are defined
+            table.add_label(label.to_string(),code_addr,expanded[index].line);
+        }
+        if line.code.is_empty() {
+            continue;
+        }
+        lines.push((code_addr,index,line.code,call.as_deref()));
+        code_addr += 1;
+    }
+    //declared variables get their addresses before any are made by being used,
+    //each follows on from the one before it once an `ORG` has placed one
+    let mut data_addr = None;
+    let mut placed = Vec::new();
+    for (index,name,values,org) in declarations {
+        match declare(&mut table,name,values,org.or(data_addr)) {
+            Ok(addrs) => {
+                data_addr = Some(addrs.end);
+                placed.push((index,values,addrs));
+            },
+            Err(e) => errors.push(operand_error(index,&expanded[index].text,e)),
+        }
+    }
+    //constants can use labels, declared variables and each other, so are worked out once every
+    //address is known, going round until no more can be
+    let mut pending = constants;
+    loop {
+        let before = pending.len();
+        pending.retain(|&(index,name,value)| match str_to_const(&table,value) {
+            Ok(v) => {
+                if let Err(message) = table.add_const(name.to_string(),v) {
+                    errors.push(Diagnostic::error(index,message));
+                }
+                false
+            },
+            Err(_) => true,
+        });
+        if pending.len() == before {
+            break;
+        }
+    }
+    errors.extend(unresolved(&table,&pending,&expanded));
+    //the values declared variables start with can use constants
+    for (index,values,addrs) in placed {
+        match str_to_data(&table,values) {
+            Ok(values) => data.extend(addrs.map(|a| a as u16).zip(values)),
+            Err(e) => errors.push(operand_error(index,&expanded[index].text,e)),
+        }
+    }
+    //instructions go in the slot for their address, with ENDs filling any gaps `ORG` leaves
+    let len = lines.iter().map(|&(addr,..)| addr + 1).max().unwrap_or(0);
+    let mut code = vec![Instruction::END;len];
+    let mut debug_info = vec![DebugInfo {line : 0, file : None, text : String::new()};len];
+    let mut calls = vec![None;len];
+    let mut firsts = vec![true;len];
+    let mut gaps = vec![true;len];
+    let mut previous = None;
+    for (addr,index,line,call) in lines {
+        //the lines a macro call expands to all come from the call's line
+        let from = (&expanded[index].file,expanded[index].line);
+        firsts[addr] = previous != Some(from);
+        previous = Some(from);
+        if !gaps[addr] {
+            errors.push(Diagnostic::error(index,format!("There is already an instruction at address {}, ORG has placed two there",addr)));
+            continue;
+        }
+        match str_to_instr(&mut table,line) {
+            Ok(instr) => {
+                code[addr] = instr;
+                debug_info[addr] = DebugInfo {
+                    line : expanded[index].line,
+                    file : expanded[index].file.clone(),
+                    text : line.to_string(),
+                };
+                calls[addr] = call.map(String::from);
+                gaps[addr] = false;
+            },
+            Err(e) => match call {
+                Some(call) => errors.push(Diagnostic::error(index,format!("{}, in the macro call '{}'",e,call))),
+                None => errors.push(operand_error(index,&expanded[index].text,e)),
+            },
+        }
+    }
+    for (index,name,format) in formats {
+        if table.table.contains_key(name) {
+            table.set_format(name.to_string(),format);
+        } else {
+            errors.push(Diagnostic::error(index,format!("@display names '{}' which is not a variable",name)));
+        }
+    }
+    diagnostics.extend(errors.into_iter().map(|e : Diagnostic| {
+        let source = &expanded[e.line];
+        //the lines a macro expands to aren't in the source, so there are no columns to point at
+        let columns = e.columns.clone().filter(|_| source.call.is_none());
+        Diagnostic {line : source.line, ..e}.in_file(source.file.clone()).at(columns)
+    }));
+    diagnostics.sort_by(|a,b| (&a.file,a.line).cmp(&(&b.file,b.line)));
+    if !diagnostics.is_empty() {
+        return Err(Diagnostics(diagnostics));
+    }
+    let mut program = Code::new(table,code,debug_info,data);
+    program.calls = calls;
+    program.firsts = firsts;
+    program.gaps = gaps;
+    program.source = included;
+    Ok(program)
+}
+
+/// Reserves the memory for a data declaration, at `addr` if given and otherwise after every other
+/// variable, returning the addresses of its cells. They are filled in once constants are known
+fn declare(table : &mut SymbolTable, name : &str, data : &str, addr : Option<usize>) -> Result<Range<usize>,LineError> {
+    let len = data_len(data)?;
+    if len == 0 {
+        return Err(format!("'{}' needs at least one value",name).into());
+    }
+    let addr = match addr {
+        Some(addr) => table.add_data_at(name.to_string(),addr,len)?,
+        None       => table.add_data(name.to_string(),len)?,
+    };
+    if table.num_vars > machine::MEMORY_SIZE {
+        return Err(format!("'{}' does not fit in memory, which has {} cells",name,machine::MEMORY_SIZE).into());
+    }
+    Ok(addr..addr + len)
+}
+
+/// A problem with the operand of the line `text`, pointing at the part of it that is wrong when
+/// that is known
+fn operand_error(index : usize, text : &str, e : LineError) -> Diagnostic {
+    let start = source::spans(text).operand.map_or(0,|operand| operand.start);
+    Diagnostic::error(index,e.message).at(e.span.map(|span| start + span.start..start + span.end))
+}
+
+/// Why each constant left over once no more could be worked out wasn't. One that uses another
+/// left over constant is either part of a cycle or waiting on one with an error of its own,
+/// which is reported instead
+fn unresolved(table : &SymbolTable, pending : &[(usize,&str,&str)], expanded : &[macros::Expanded]) -> Vec<Diagnostic> {
+    let uses = |name : &str| -> Vec<&str> {
+        let value = pending.iter().find(|&&(_,n,_)| n == name).map_or("",|&(_,_,v)| v);
+        source::identifiers(value).into_iter().map(|r| &value[r])
+            .filter(|used| pending.iter().any(|&(_,n,_)| n == *used)).collect()
+    };
+    let mut errors = Vec::new();
+    for &(index,name,value) in pending {
+        if uses(name).is_empty() {
+            if let Err(e) = str_to_const(table,value) {
+                errors.push(operand_error(index,&expanded[index].text,e));
+            }
+            continue;
+        }
+        //a search through what the constant uses for a way back to it
+        let mut from = HashMap::new();
+        let mut queue = VecDeque::from(vec![name]);
+        let mut last = None;
+        'search : while let Some(at) = queue.pop_front() {
+            for used in uses(at) {
+                if used == name {
+                    last = Some(at);
+                    break 'search;
+                }
+                if let Entry::Vacant(e) = from.entry(used) {
+                    e.insert(at);
+                    queue.push_back(used);
+                }
+            }
+        }
+        if let Some(mut at) = last {
+            let mut cycle = vec![name];
+            while at != name {
+                cycle.push(at);
+                at = from[at];
+            }
+            cycle[1..].reverse();
+            cycle.push(name);
+            errors.push(Diagnostic::error(index,format!("'{}' is defined in terms of itself ({})",name,cycle.join(" -> "))));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown_as_char(src : &str) -> Result<bool,String> {
+        let program = assemble(src).map_err(|e| e.to_string())?;
+        Ok(matches!(program.symbols().format("X"),Some(VarFormat::Char)))
+    }
+
+    #[test]
+    fn display_annotation() {
+        assert_eq!(shown_as_char("LDD X ; @display X char\nEND"),Ok(true));
+        assert_eq!(shown_as_char("LDD X ;@display\tX char\nEND"),Ok(true));
+        assert!(shown_as_char("LDD X ; @display\nEND").is_err());
+    }
+
+    #[test]
+    fn display_must_be_a_whole_word() {
+        assert_eq!(shown_as_char("LDD X ; @displayX char\nEND"),Ok(false));
+        assert_eq!(shown_as_char("LDD X ; @displayed here\nEND"),Ok(false));
+    }
+}
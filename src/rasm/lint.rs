@@ -0,0 +1,229 @@
+//! Warnings about common mistakes that can be found without running a program.
+use std::collections::{HashMap,HashSet};
+use super::{cfg,literal,AdrMode,Code,Diagnostic,Instruction};
+
+/// Checks an assembled program, returning warnings in line order
+pub fn lint(code : &Code) -> Vec<Diagnostic> {
+    let len = code.code.len();
+    if len == 0 {
+        return vec![Diagnostic::warning(0,String::from("the program has no instructions"))];
+    }
+    let line = |pc : usize| code.debug_info[pc].line;
+    let at = |pc : usize, message : String| {
+        let info = &code.debug_info[pc];
+        Diagnostic::warning(info.line,message).in_file(info.file.clone())
+    };
+    let mut warnings = Vec::new();
+    let reachable = cfg::reachable(code);
+
+    if !code.code.iter().enumerate().any(|(pc,i)| matches!(i,Instruction::END) && !code.is_gap(pc)) {
+        warnings.push(at(len - 1,String::from("the program has no END")));
+    } else if reachable[len - 1] && !matches!(code.code[len - 1],Instruction::END | Instruction::JMP {..}) {
+        warnings.push(at(len - 1,String::from("execution can run past the last instruction, add END after it")));
+    }
+
+    let mut pc = 0;
+    //addresses ORG skipped over aren't instructions, so can't be unreachable ones
+    let unreachable = |pc : usize| !reachable[pc] && !code.is_gap(pc);
+    while pc < len {
+        if !unreachable(pc) {
+            pc += 1;
+            continue;
+        }
+        let start = pc;
+        while pc < len && unreachable(pc) {
+            pc += 1;
+        }
+        let message = if start + 1 == pc {
+            String::from("this instruction can never be reached")
+        } else {
+            format!("lines {}-{} can never be reached",line(start) + 1,line(pc - 1) + 1)
+        };
+        warnings.push(at(start,message));
+    }
+
+    let mut targets = HashSet::new();
+    for (pc,instr) in code.code.iter().enumerate() {
+        let addr = match *instr {
+            Instruction::JMP {addr} | Instruction::JPE {addr} | Instruction::JPN {addr} => addr as usize,
+            _ => continue,
+        };
+        targets.insert(addr);
+        let text = &code.debug_info[pc].text;
+        let operand = text[instr.mnemonic().len()..].trim();
+        let numeric = literal::is_number(operand);
+        if addr >= len && numeric {
+            warnings.push(at(pc,format!("'{}' jumps past the end of the program",text)));
+        } else if addr >= len {
+            warnings.push(at(pc,format!("'{}' jumps to a label with no instruction after it",text)));
+        } else if code.is_gap(addr) {
+            warnings.push(at(pc,format!("'{}' jumps to address {} which ORG left without an instruction",text,addr)));
+        } else if numeric {
+            warnings.push(at(pc,
+                format!("'{}' jumps to instruction {} which is on line {}, use a label to jump to a line",text,addr,line(addr) + 1)));
+        }
+    }
+    for (name,addr,def) in code.table.labels() {
+        if !targets.contains(&addr) {
+            let file = code.debug_info.get(addr).and_then(|info| info.file.clone());
+            warnings.push(Diagnostic::warning(def,format!("label '{}' is never jumped to",name)).in_file(file));
+        }
+    }
+
+    for (pc,instr) in code.code.iter().enumerate() {
+        if reachable[pc] && matches!(instr,Instruction::CMP {..})
+            && !matches!(code.code.get(pc + 1),Some(Instruction::JPE {..}) | Some(Instruction::JPN {..})) {
+            warnings.push(at(pc,String::from("CMP is not followed by JPE or JPN so the comparison is never used")));
+        }
+    }
+
+    warnings.extend(read_before_store(code,&reachable));
+    warnings.sort_by(|a,b| (&a.file,a.line).cmp(&(&b.file,b.line)));
+    warnings
+}
+
+/// The address an instruction reads from memory, not counting indexed reads of arrays
+fn reads(instr : &Instruction) -> Option<u16> {
+    match *instr {
+        Instruction::LOAD {data, adr_mode : AdrMode::Direct}
+        | Instruction::LOAD {data, adr_mode : AdrMode::Indirect} => Some(data),
+        Instruction::ADD {rhs, adr_mode : AdrMode::Direct} | Instruction::SUB {rhs, adr_mode : AdrMode::Direct}
+        | Instruction::CMP {rhs, adr_mode : AdrMode::Direct} | Instruction::XOR {rhs, adr_mode : AdrMode::Direct}
+        | Instruction::OR {rhs, adr_mode : AdrMode::Direct} => Some(rhs),
+        _ => None,
+    }
+}
+
+/// Finds variables read at a point where there is a path from the start that never stored to them
+fn read_before_store(code : &Code, reachable : &[bool]) -> Vec<Diagnostic> {
+    let len = code.code.len();
+    let vars : HashMap<u16,&str> = code.table.vars().map(|(name,addr)| (addr as u16,name)).collect();
+    //the variables stored to on every path to each instruction, None until a path is found
+    let mut stored : Vec<Option<HashSet<u16>>> = vec![None;len];
+    //declared variables start with a value
+    stored[code.start()] = Some(code.data.iter().map(|&(addr,_)| addr).collect());
+    let mut todo = vec![code.start()];
+    while let Some(pc) = todo.pop() {
+        let mut out = stored[pc].clone().unwrap();
+        if let Instruction::STO(addr) = code.code[pc] {
+            out.insert(addr);
+        }
+        for next in cfg::successors(code,pc).into_iter().filter(|&n| n < len) {
+            let merged = match &stored[next] {
+                Some(before) => before.intersection(&out).copied().collect(),
+                None         => out.clone(),
+            };
+            if stored[next].as_ref() != Some(&merged) {
+                stored[next] = Some(merged);
+                todo.push(next);
+            }
+        }
+    }
+    let mut warned = HashSet::new();
+    let mut warnings = Vec::new();
+    for (pc,instr) in code.code.iter().enumerate() {
+        let addr = match reads(instr) {
+            Some(addr) if reachable[pc] => addr,
+            _ => continue,
+        };
+        let name = match vars.get(&addr) {
+            Some(name) => name,
+            None => continue,
+        };
+        if !stored[pc].as_ref().is_some_and(|s| s.contains(&addr)) && warned.insert(addr) {
+            let info = &code.debug_info[pc];
+            warnings.push(Diagnostic::warning(info.line,
+                format!("'{}' may be read before anything is stored in it",name)).in_file(info.file.clone()));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The warnings for a program, each with its line counting from 1
+    fn warnings(src : &str) -> Vec<(usize,String)> {
+        let code = crate::assemble(src).unwrap();
+        lint(&code).into_iter().map(|w| (w.line + 1,w.message)).collect()
+    }
+
+    fn warns(src : &str, message : &str) -> bool {
+        warnings(src).iter().any(|(_,m)| m.contains(message))
+    }
+
+    #[test]
+    fn clean_program() {
+        assert_eq!(warnings("LDM #3\nLOOP: DEC ACC\nCMP #0\nJPN LOOP\nSTO X\nLDD X\nEND"),vec![]);
+    }
+
+    #[test]
+    fn no_instructions() {
+        assert_eq!(warnings(""),vec![(1,String::from("the program has no instructions"))]);
+        assert!(!warns("END","no instructions"));
+    }
+
+    #[test]
+    fn no_end() {
+        assert_eq!(warnings("LDM #1\nOUT"),vec![(2,String::from("the program has no END"))]);
+        assert!(!warns("LDM #1\nOUT\nEND","no END"));
+    }
+
+    #[test]
+    fn running_past_the_last_instruction() {
+        let message = "execution can run past the last instruction";
+        assert!(warns("LDM #1\nCMP #1\nJPE L\nEND\nL: OUT",message));
+        assert!(!warns("LDM #1\nOUT\nEND",message));
+        //a jump back can end the program too
+        assert!(!warns("LDM #1\nCMP #1\nJPE L\nEND\nL: OUT\nJMP L",message));
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(warnings("JMP L\nOUT\nL: END"),vec![(2,String::from("this instruction can never be reached"))]);
+        assert_eq!(warnings("JMP L\nOUT\nIN\nL: END"),vec![(2,String::from("lines 2-3 can never be reached"))]);
+        assert!(!warns("LDM #1\nCMP #1\nJPE L\nOUT\nL: END","never be reached"));
+    }
+
+    #[test]
+    fn jumps_by_number() {
+        assert!(warns("JMP 1\nEND","jumps to instruction 1 which is on line 2, use a label"));
+        assert!(warns("JMP 9\nEND","jumps past the end of the program"));
+        assert!(!warns("JMP L\nL: END","jumps"));
+    }
+
+    #[test]
+    fn jump_to_a_label_with_nothing_after_it() {
+        assert!(warns("LDM #1\nCMP #1\nJPE L\nEND\nL:","jumps to a label with no instruction after it"));
+    }
+
+    #[test]
+    fn jump_into_an_org_gap() {
+        assert!(warns("JMP 5\nORG 8\nEND","jumps to address 5 which ORG left without an instruction"));
+    }
+
+    #[test]
+    fn label_never_jumped_to() {
+        assert_eq!(warnings("START: LDM #1\nEND"),vec![(1,String::from("label 'START' is never jumped to"))]);
+        assert!(!warns("START: LDM #1\nJMP START\nEND","never jumped to"));
+    }
+
+    #[test]
+    fn comparison_never_used() {
+        let message = "CMP is not followed by JPE or JPN";
+        assert!(warns("LDM #1\nCMP #1\nOUT\nEND",message));
+        assert!(!warns("LDM #1\nCMP #1\nJPN L\nL: END",message));
+    }
+
+    #[test]
+    fn read_before_store() {
+        let message = "'X' may be read before anything is stored in it";
+        assert_eq!(warnings("LDD X\nEND"),vec![(1,String::from(message))]);
+        assert!(!warns("LDM #1\nSTO X\nLDD X\nEND",message));
+        //declared variables start with a value
+        assert!(!warns("LDD X\nEND\nX: #5",message));
+        //stored on only one of the paths to the read
+        assert!(warns("LDM #1\nCMP #1\nJPE L\nSTO X\nL: LDD X\nEND",message));
+    }
+}
@@ -0,0 +1,165 @@
+//! A listing of an assembled program, every source line with the address, machine code and
+//! instruction it became, then the memory cells data declarations fill, followed by the symbol
+//! table. Instructions and memory are numbered separately, so their addresses are kept apart.
+use std::{collections::{HashMap,HashSet},path::Path};
+use super::{object,source::Line,Code};
+
+/// One row of the listing, empty strings where a column has nothing
+struct Row<'a> {
+    addr : String,
+    words : String,
+    instr : String,
+    location : String,
+    text : &'a str,
+}
+
+/// The listing of `code`, made from the lines it was assembled from
+pub fn listing(code : &Code) -> String {
+    //the instructions made from each line, more than one for a macro call
+    let mut made : HashMap<(Option<&Path>,usize),Vec<usize>> = HashMap::new();
+    for (pc,info) in code.debug_info.iter().enumerate().filter(|&(pc,_)| !code.is_gap(pc)) {
+        made.entry((info.file.as_deref(),info.line)).or_default().push(pc);
+    }
+    let table = code.symbols();
+    let starts : HashSet<usize> = table.vars().map(|(_,addr)| addr).collect();
+    //every line is listed with its instructions, and data declarations again with their cells
+    let mut rows = Vec::new();
+    let mut data_rows = Vec::new();
+    for line in &code.source {
+        let instrs : Vec<(String,String,String)> = made.get(&(line.file.as_deref(),line.line)).into_iter().flatten()
+            .map(|&pc| {
+                let instr = &code.code[pc];
+                let words = object::encode(instr);
+                (pc.to_string(),format!("{:04X} {:04X}",words[0],words[1]),instr.to_string())
+            })
+            .collect();
+        let location = match &line.file {
+            Some(file) => format!("{}:{}",file.display(),line.line + 1),
+            None       => (line.line + 1).to_string(),
+        };
+        let parsed = Line::parse(&line.text);
+        if let Some(start) = parsed.label.filter(|_| parsed.is_data()).and_then(|name| table.lookup(name)) {
+            push_rows(&mut data_rows,declared(code,start as usize,&starts),&location,&line.text);
+        }
+        push_rows(&mut rows,instrs,&location,&line.text);
+    }
+
+    let mut out = columns(["CODE","INSTRUCTION"],rows);
+    if !data_rows.is_empty() {
+        out.push_str("\nDATA\n");
+        out.push_str(&columns(["WORD","VALUE"],data_rows));
+    }
+
+    //labels are instruction addresses, variables memory addresses and constants their value
+    let mut symbols : Vec<(&str,&str,i32)> = table.labels().map(|(name,addr,_)| ("label",name,addr as i32))
+        .chain(table.vars().map(|(name,addr)| ("variable",name,addr as i32)))
+        .chain(table.constants().map(|(name,value)| ("constant",name,value as i32)))
+        .collect();
+    let order = |kind : &str| ["label","variable","constant"].iter().position(|&k| k == kind);
+    symbols.sort_by(|a,b| (order(a.0),a.2,a.1).cmp(&(order(b.0),b.2,b.1)));
+    let name_width = symbols.iter().map(|s| s.1.len()).max().unwrap_or(0).max("NAME".len());
+    out.push_str(&format!("\nSYMBOLS\n{:<nw$}  {:<8}  ADDRESS/VALUE\n","NAME","KIND",nw = name_width));
+    for (kind,name,value) in symbols {
+        out.push_str(&format!("{:<nw$}  {:<8}  {}\n",name,kind,value,nw = name_width));
+    }
+    out
+}
+
+/// The rows for a line, one per cell it made, or one with only the line if it made none
+fn push_rows<'a>(rows : &mut Vec<Row<'a>>, mut cells : Vec<(String,String,String)>, location : &str, text : &'a str) {
+    if cells.is_empty() {
+        cells.push(Default::default());
+    }
+    for (i,(addr,words,instr)) in cells.into_iter().enumerate() {
+        //a line that made several cells is only written next to the first
+        let (location,text) = if i == 0 {(location.to_string(),text)} else {(String::new(),"")};
+        rows.push(Row {addr,words,instr,location,text});
+    }
+}
+
+/// Rows lined up under a heading, `names` being what the machine code and meaning columns hold
+fn columns(names : [&str;2], rows : Vec<Row>) -> String {
+    let width = |cell : fn(&Row) -> usize, name : &str| rows.iter().map(cell).max().unwrap_or(0).max(name.len());
+    let words_width = width(|r| r.words.len(),names[0]);
+    let instr_width = width(|r| r.instr.len(),names[1]);
+    let location_width = width(|r| r.location.len(),"LINE");
+    let mut out = format!("ADDR  {:<ww$}  {:<iw$}  {:>lw$}  SOURCE\n",names[0],names[1],"LINE",
+        ww = words_width,iw = instr_width,lw = location_width);
+    for row in &rows {
+        let line = format!("{:>4}  {:<ww$}  {:<iw$}  {:>lw$}  {}",row.addr,row.words,row.instr,row.location,row.text,
+            ww = words_width,iw = instr_width,lw = location_width);
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// The cells of the data declaration starting at `start`, which ends where the memory another
+/// declaration was given starts
+fn declared(code : &Code, start : usize, starts : &HashSet<usize>) -> Vec<(String,String,String)> {
+    let first = match code.data.iter().position(|&(addr,_)| addr as usize == start) {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    code.data[first..].iter().enumerate()
+        .take_while(|&(i,&(addr,_))| addr as usize == start + i && (i == 0 || !starts.contains(&(addr as usize))))
+        .map(|(_,&(addr,value))| (addr.to_string(),format!("{:04X}",value as u16),format!("#{}",value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(src : &str) -> String {
+        listing(&crate::assemble(src).unwrap())
+    }
+
+    #[test]
+    fn code_data_and_symbols() {
+        let src = "START: LDM #1\n  JMP L ; over the gap\nORG 4\nL: OUT\n  END\nX: #5,#-1\nMSG: \"HI\"\nK EQU 7\n";
+        assert_eq!(list(src),"\
+ADDR  CODE       INSTRUCTION  LINE  SOURCE
+   0  0101 0001  LDM #1          1  START: LDM #1
+   1  0C00 0004  JMP 4           2    JMP L ; over the gap
+                                 3  ORG 4
+   4  1100 0000  OUT             4  L: OUT
+   5  1200 0000  END             5    END
+                                 6  X: #5,#-1
+                                 7  MSG: \"HI\"
+                                 8  K EQU 7
+
+DATA
+ADDR  WORD  VALUE  LINE  SOURCE
+   4  0005  #5        6  X: #5,#-1
+   5  FFFF  #-1
+   6  0048  #72       7  MSG: \"HI\"
+   7  0049  #73
+
+SYMBOLS
+NAME   KIND      ADDRESS/VALUE
+START  label     0
+L      label     4
+X      variable  4
+MSG    variable  6
+K      constant  7
+");
+    }
+
+    #[test]
+    fn no_data_section_without_declarations() {
+        let out = list("LDM #1\nSTO X\nEND");
+        assert!(!out.contains("DATA"),"{}",out);
+        assert!(out.contains("\nX     variable  0\n"),"{}",out);
+    }
+
+    #[test]
+    fn macro_call_lists_each_instruction() {
+        let out = list("MACRO TWICE\nOUT\nOUT\nENDM\n  TWICE\nEND");
+        assert!(out.contains("\
+   0  1100 0000  OUT             5    TWICE
+   1  1100 0000  OUT
+   2  1200 0000  END             6  END
+"),"{}",out);
+    }
+}
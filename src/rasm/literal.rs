@@ -0,0 +1,272 @@
+//! Numbers and characters written in a program.
+//!
+//! Numbers are denary, with a `-` in front if negative, binary (`B1010` or `0b1010`) or hex
+//! (`&FF` or `0xFF`), and long ones can be split up with `_`, like `B1111_0000`. A word holds a
+//! number that fits either signed or unsigned, so in 16 bits `-1` and `65535` are the same.
+//! Characters go in single quotes and can be an escape: `'\n'`, `'\t'`, `'\r'`, `'\0'`, `'\\'`,
+//! `'\''` or `'\"'`, which also work in strings.
+use std::{fmt,ops::Range};
+
+/// Why a literal can't be read, and which part of it is wrong
+pub struct LiteralError {
+    /// A byte range into the literal
+    pub span : Range<usize>,
+    pub message : String,
+}
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+fn error<T>(span : Range<usize>, message : String) -> Result<T,LiteralError> {
+    Err(LiteralError {span, message})
+}
+
+/// Whether `text` is a number rather than a name. `B101` is a binary number but `B12` is a name
+pub fn is_number(text : &str) -> bool {
+    let text = text.strip_prefix('-').unwrap_or(text);
+    text.starts_with(|c : char| c.is_ascii_digit() || c == '&')
+        || text.strip_prefix('B').is_some_and(|b| b.starts_with(['0','1']) && b.chars().all(|c| matches!(c,'0' | '1' | '_')))
+}
+
+/// Whether `value` fits in a word of `bits` bits, as either a signed or an unsigned number
+pub fn fits(value : i64, bits : u32) -> bool {
+    -(1 << (bits - 1)) <= value && value < 1 << bits
+}
+
+/// Reads a number, which has to fit in a word of `bits` bits
+pub fn number(text : &str, bits : u32) -> Result<i32,LiteralError> {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    let (radix,name,digits) = if let Some(d) = ["0x","0X","&"].iter().find_map(|p| unsigned.strip_prefix(p)) {
+        (16,"hex",d)
+    } else if let Some(d) = ["0b","0B","B"].iter().find_map(|p| unsigned.strip_prefix(p)) {
+        (2,"binary",d)
+    } else {
+        (10,"denary",unsigned)
+    };
+    let start = text.len() - digits.len();
+    if digits.is_empty() {
+        return error(0..text.len(),format!("'{}' needs digits after it",text));
+    }
+    let mut value : i64 = 0;
+    //so that a '_' at the start is caught
+    let mut separator = true;
+    for (i,c) in digits.char_indices() {
+        let at = start + i..start + i + c.len_utf8();
+        if c == '_' {
+            if separator {
+                return error(at,String::from("A '_' can only go between digits"));
+            }
+            separator = true;
+            continue;
+        }
+        match c.to_digit(radix) {
+            //anything this big is too big already, stopping here means it can't overflow
+            Some(d) => value = (value * radix as i64 + d as i64).min(1 << 40),
+            None => return error(at,format!("'{}' is not a {} digit",c,name)),
+        }
+        separator = false;
+    }
+    if separator {
+        return error(text.len() - 1..text.len(),String::from("A '_' can only go between digits"));
+    }
+    let value = if unsigned.len() < text.len() {-value} else {value};
+    if !fits(value,bits) {
+        return error(0..text.len(),
+            format!("{} does not fit in a {} bit word, which holds {} to {}",text,bits,-(1i64 << (bits - 1)),(1i64 << bits) - 1));
+    }
+    Ok(value as i32)
+}
+
+/// The character an escape like `\n` stands for, given what follows the `\`
+fn escape(c : char) -> Option<char> {
+    Some(match c {
+        'n'  => '\n',
+        't'  => '\t',
+        'r'  => '\r',
+        '0'  => '\0',
+        '\\' => '\\',
+        '\'' => '\'',
+        '"'  => '"',
+        _    => return None,
+    })
+}
+
+/// The characters between the quotes of a character or string literal, with escapes worked out.
+/// `start` is where they begin in the literal, for error spans
+fn unescape(inner : &str, start : usize) -> Result<Vec<i32>,LiteralError> {
+    let mut values = Vec::new();
+    let mut chars = inner.char_indices();
+    while let Some((i,c)) = chars.next() {
+        let at = start + i;
+        let c = match c {
+            '\\' => match chars.next() {
+                Some((_,e)) => match escape(e) {
+                    Some(c) => c,
+                    None => return error(at..at + 1 + e.len_utf8(),
+                        format!("'\\{}' is not an escape, use \\n, \\t, \\r, \\0, \\\\, \\' or \\\"",e)),
+                },
+                None => return error(at..at + 1,String::from("'\\' needs a character after it, use '\\\\' for a backslash")),
+            },
+            c => c,
+        };
+        if !c.is_ascii() {
+            return error(at..at + c.len_utf8(),format!("'{}' is not an ASCII character",c));
+        }
+        values.push(c as i32);
+    }
+    Ok(values)
+}
+
+/// Reads a character in single quotes, like `'A'` or `'\n'`
+pub fn character(text : &str) -> Result<i32,LiteralError> {
+    let inner = match text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        Some(inner) if quoted_len(text) == Some(text.len()) => inner,
+        _ => return error(0..text.len(),String::from("A character goes in single quotes, like 'A'")),
+    };
+    match unescape(inner,1)?[..] {
+        [c] => Ok(c),
+        [] => error(0..text.len(),String::from("There is no character between the quotes")),
+        _ => error(0..text.len(),String::from("A character literal holds one character, use a string in double quotes for more")),
+    }
+}
+
+/// Reads a string in double quotes, like `"HELLO\n"`, as the value of each character
+pub fn string(text : &str) -> Result<Vec<i32>,LiteralError> {
+    match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(inner) if quoted_len(text) == Some(text.len()) => unescape(inner,1),
+        _ => error(0..text.len(),String::from("A string goes in double quotes, like \"HELLO\"")),
+    }
+}
+
+/// How long the character or string literal at the start of `text` is, up to and including its
+/// closing quote. None if it isn't closed
+pub fn quoted_len(text : &str) -> Option<usize> {
+    let quote = text.chars().next().filter(|&c| c == '\'' || c == '"')?;
+    let mut escaped = false;
+    for (i,c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of a number in a 16 bit word, or the span of what is wrong with it
+    fn word(text : &str) -> Result<i32,Range<usize>> {
+        number(text,16).map_err(|e| e.span)
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(word("0"),Ok(0));
+        assert_eq!(word("42"),Ok(42));
+        assert_eq!(word("-5"),Ok(-5));
+        assert_eq!(word("B101"),Ok(5));
+        assert_eq!(word("0b1111_0000"),Ok(240));
+        assert_eq!(word("&FF"),Ok(255));
+        assert_eq!(word("0xff"),Ok(255));
+        assert_eq!(word("-&10"),Ok(-16));
+        assert_eq!(word("1_000"),Ok(1000));
+    }
+
+    #[test]
+    fn word_range() {
+        assert_eq!(word("-32768"),Ok(-32768));
+        assert_eq!(word("65535"),Ok(65535));
+        assert_eq!(word("&FFFF"),Ok(65535));
+        assert_eq!(word("65536"),Err(0..5));
+        assert_eq!(word("-32769"),Err(0..6));
+        //far too big to fit, without overflowing while it is read
+        assert_eq!(word("99999999999999999999"),Err(0..20));
+        assert_eq!(number("255",8).ok(),Some(255));
+        assert_eq!(number("256",8).ok(),None);
+    }
+
+    #[test]
+    fn bad_numbers() {
+        assert_eq!(word("B12"),Err(2..3));
+        assert_eq!(word("&FG"),Err(2..3));
+        assert_eq!(word("12A"),Err(2..3));
+        assert_eq!(word("&"),Err(0..1));
+        assert_eq!(word("-0x"),Err(0..3));
+        assert_eq!(word("1__0"),Err(2..3));
+        assert_eq!(word("_1"),Err(0..1));
+        assert_eq!(word("&_F"),Err(1..2));
+        assert_eq!(word("1_"),Err(1..2));
+    }
+
+    #[test]
+    fn numbers_and_names() {
+        assert!(is_number("5"));
+        assert!(is_number("-5"));
+        assert!(is_number("&FF"));
+        assert!(is_number("0x1F"));
+        assert!(is_number("B101"));
+        assert!(is_number("B1_0"));
+        assert!(is_number("-B1"));
+        assert!(!is_number("B12"));
+        assert!(!is_number("B"));
+        assert!(!is_number("BIT"));
+        assert!(!is_number("MAX"));
+        assert!(!is_number("_1"));
+        assert!(!is_number("'A'"));
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(character("'A'").ok(),Some(65));
+        assert_eq!(character("' '").ok(),Some(32));
+        assert_eq!(character("'\\n'").ok(),Some(10));
+        assert_eq!(character("'\\0'").ok(),Some(0));
+        assert_eq!(character("'\\\\'").ok(),Some(92));
+        assert_eq!(character("'\\''").ok(),Some(39));
+        assert_eq!(character("'\"'").ok(),Some(34));
+    }
+
+    #[test]
+    fn bad_characters() {
+        let span = |text : &str| character(text).err().map(|e| e.span);
+        assert_eq!(span("''"),Some(0..2));
+        assert_eq!(span("'AB'"),Some(0..4));
+        assert_eq!(span("'A"),Some(0..2));
+        assert_eq!(span("'\\'"),Some(0..3));
+        assert_eq!(span("'\\q'"),Some(1..3));
+        assert_eq!(span("'é'"),Some(1..3));
+        assert_eq!(span("A"),Some(0..1));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(string("\"HI\"").ok(),Some(vec![72,73]));
+        assert_eq!(string("\"\"").ok(),Some(vec![]));
+        assert_eq!(string("\"A\\n\"").ok(),Some(vec![65,10]));
+        assert_eq!(string("\"\\\"\"").ok(),Some(vec![34]));
+        assert_eq!(string("\"it's\"").ok(),Some(vec![105,116,39,115]));
+        assert_eq!(string("\"AB\\q\"").err().map(|e| e.span),Some(3..5));
+        assert_eq!(string("\"AB").err().map(|e| e.span),Some(0..3));
+        assert_eq!(string("\"A\"B\"").err().map(|e| e.span),Some(0..5));
+    }
+
+    #[test]
+    fn quoted_lengths() {
+        assert_eq!(quoted_len("'A'"),Some(3));
+        assert_eq!(quoted_len("'\\''"),Some(4));
+        assert_eq!(quoted_len("'\\\\', #1"),Some(4));
+        assert_eq!(quoted_len("\"A,B\" ; comment"),Some(5));
+        assert_eq!(quoted_len("\"it's\""),Some(6));
+        assert_eq!(quoted_len("'A"),None);
+        assert_eq!(quoted_len("'\\'"),None);
+        assert_eq!(quoted_len("A"),None);
+        assert_eq!(quoted_len(""),None);
+    }
+}
@@ -0,0 +1,358 @@
+use super::{Code,DebugInfo,Program,Instruction,DisplayStyle,cpu::*,mem::Memory,computer::{Computer,ComputerBuilder},explain,fde::{self,Phase,Transfer}};
+use std::{
+    fmt::{self,Display,Formatter},
+    io::{self,Read,Write,Stdin,Stdout},
+};
+
+/// Steps a program may run for by default, 0 means no limit
+pub const DEFAULT_MAX_STEPS : usize = 100_000;
+
+/// Stops programs that run unattended from going on forever.
+///
+/// As well as enforcing a step limit it watches for the machine coming back to a state (PC,
+/// registers, flags and memory) it has been in before. That means the program can never finish,
+/// unless it read input in between, which is why `IN` starts the search again. States are
+/// compared using Brent's algorithm, so only one is kept however long the program runs.
+struct Watchdog {
+    max_steps : usize,
+    steps : usize,
+    detect_loops : bool,
+    /// The state looked for again, taken at steps that get twice as far apart each time
+    saved : Option<State>,
+    /// Steps since `saved` was taken, and how many to take before it is replaced
+    since : usize,
+    wait : usize,
+    /// The first and last source lines executed since `saved` was taken
+    lines : Option<(usize,usize)>,
+}
+/// PC, ACC, IX, flags and memory
+type State = (u16,i16,i16,u8,Vec<i16>);
+impl Watchdog {
+    fn new(max_steps : usize, detect_loops : bool) -> Self {
+        Self {
+            max_steps,
+            steps : 0,
+            detect_loops,
+            saved : None,
+            since : 0,
+            wait : 0,
+            lines : None,
+        }
+    }
+    /// Called before each instruction is executed
+    fn check<I : Read,O : Write,const N: usize>(&mut self, code : &Code, cpu : &CPU<I,O,N>) -> Result<(),Fault> {
+        if self.steps == self.max_steps && self.max_steps != 0 {
+            return Err(Fault::StepLimit(self.max_steps));
+        }
+        self.steps += 1;
+        if !self.detect_loops {
+            return Ok(());
+        }
+        if let Some((Instruction::IO(true),_)) = code.get(cpu.pc() as usize) {
+            self.saved = None;
+            return Ok(());
+        }
+        let line = code.get(cpu.pc() as usize).map_or(0,|(_,info)| info.line);
+        let cells = cpu.memory.cells();
+        if let Some((pc,acc,ix,flags,memory)) = &self.saved {
+            if (*pc,*acc,*ix,*flags) == (cpu.pc(),cpu.acc(),cpu.ix(),cpu.flags()) && memory[..] == *cells {
+                let (from,to) = self.lines.unwrap_or((line,line));
+                return Err(Fault::InfiniteLoop {line,from,to});
+            }
+        }
+        if self.saved.is_none() || self.since == self.wait {
+            self.wait = if self.saved.is_none() {1} else {self.wait * 2};
+            self.saved = Some((cpu.pc(),cpu.acc(),cpu.ix(),cpu.flags(),cells.to_vec()));
+            self.since = 0;
+            self.lines = None;
+        }
+        self.since += 1;
+        self.lines = Some(self.lines.map_or((line,line),|(from,to)| (from.min(line),to.max(line))));
+        Ok(())
+    }
+}
+
+/// Words of memory every machine has
+pub const MEMORY_SIZE : usize = 100;
+/// Bits in a memory cell and in a register, every value written in a program has to fit
+pub const WORD_BITS : u32 = 16;
+
+/// How a [`Machine`] should behave
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// How the registers and memory are shown when the machine is displayed
+    pub style : DisplayStyle,
+    /// Steps [`Machine::run`] may take before giving up, 0 means no limit
+    pub max_steps : usize,
+    /// Whether to stop a program that comes back to a state it was in before, as it can never finish
+    pub detect_loops : bool,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            style : DisplayStyle::Denary,
+            max_steps : DEFAULT_MAX_STEPS,
+            detect_loops : true,
+        }
+    }
+}
+
+/// An assembled program loaded into a computer, ready to be stepped through or run.
+///
+/// `IN` reads a character per line from `I` and `OUT` writes to `O`.
+pub struct Machine<I : Read = Stdin,O : Write = Stdout> {
+    program : Program,
+    computer : Computer<I,O,MEMORY_SIZE>,
+    watchdog : Watchdog,
+    /// The next part of the fetch-decode-execute cycle [`Machine::micro_step`] will carry out
+    stage : usize,
+    /// The address the last instruction executed stored to
+    stored : Option<u16>,
+    /// The address of the instruction being executed, or the last one that was
+    executing : Option<u16>,
+}
+impl Machine {
+    /// A machine doing its input and output on the terminal
+    pub fn new(program : Program, config : Config) -> Self {
+        Self::with_io(program,config,io::stdin(),io::stdout())
+    }
+}
+impl<I : Read,O : Write> Machine<I,O> {
+    pub fn with_io(program : Program, config : Config, input : I, output : O) -> Self {
+        let table = program.symbols();
+        let mut mem = Memory::new(table.addresses(),config.style);
+        mem.load(&program.data);
+        let mut cpu = CPU::new(mem,output,input);
+        cpu.set_pc(program.start() as u16);
+        let computer = ComputerBuilder::new()
+            .attach_cpu(cpu)
+            .display_style(config.style)
+            .build()
+            .unwrap();
+        Self {
+            program,
+            computer,
+            watchdog : Watchdog::new(config.max_steps,config.detect_loops),
+            stage : 0,
+            stored : None,
+            executing : None,
+        }
+    }
+    /// Executes the next instruction, doing nothing once the program has ended
+    pub fn step(&mut self) -> Result<(),Fault> {
+        if self.done() {
+            return Ok(());
+        }
+        while self.mid_cycle() {
+            self.micro_step()?;
+        }
+        if self.done() {
+            return Ok(());
+        }
+        let cpu = &mut self.computer.cpu;
+        let program = &self.program;
+        let (instr,_) = program.get(cpu.pc() as usize)
+            .ok_or_else(|| missing(program,cpu.pc()))?;
+        self.executing = Some(cpu.pc());
+        self.watchdog.check(&self.program,cpu)?;
+        self.stored = match *instr {
+            Instruction::STO(addr) => Some(addr),
+            _ => None,
+        };
+        cpu.execute(instr)
+    }
+    /// Executes the next instruction like [`Machine::step`], describing what it did in plain English
+    pub fn step_explained(&mut self) -> Result<String,Fault> {
+        let instr = match self.current() {
+            Some((instr,_)) if !self.done() => instr.clone(),
+            _ => return self.step().map(|_| String::new()),
+        };
+        let before = explain::Before::of(&self.computer.cpu);
+        self.step()?;
+        Ok(explain::explain(&instr,&before,&self.computer.cpu,self.program.symbols()))
+    }
+    /// Carries out the next part of the fetch-decode-execute cycle, returning the register
+    /// transfers made. Each fetch transfer and the decode are a part each, then the whole
+    /// execute phase is one. MAR, MDR and CIR are only kept up to date by micro stepping
+    pub fn micro_step(&mut self) -> Result<Vec<Transfer>,Fault> {
+        if self.done() {
+            return Ok(Vec::new());
+        }
+        match self.stage_step() {
+            Ok(transfers) => {
+                self.stage = (self.stage + 1) % 6;
+                Ok(transfers)
+            },
+            Err(fault) => {
+                //a fault ends the cycle, the next one starts with a fetch rather than going over this one again
+                self.stage = 0;
+                Err(fault)
+            },
+        }
+    }
+    /// Carries out the part of the cycle `stage` is at, without moving on to the next part
+    fn stage_step(&mut self) -> Result<Vec<Transfer>,Fault> {
+        let cpu = &mut self.computer.cpu;
+        let fetch = |n : usize| vec![Transfer::new(Phase::Fetch,fde::FETCH[n])];
+        let transfers = match self.stage {
+            0 => {
+                let program = &self.program;
+                program.get(cpu.pc() as usize).ok_or_else(|| missing(program,cpu.pc()))?;
+                self.executing = Some(cpu.pc());
+                self.watchdog.check(&self.program,cpu)?;
+                self.stored = None;
+                cpu.mar = cpu.pc();
+                fetch(0)
+            },
+            1 => {
+                cpu.increment_pc();
+                fetch(1)
+            },
+            2 => {
+                cpu.mdr = Word::Instr(self.program.code[cpu.mar as usize].clone());
+                fetch(2)
+            },
+            3 => {
+                if let Word::Instr(instr) = &cpu.mdr {
+                    cpu.cir = Some(instr.clone());
+                }
+                fetch(3)
+            },
+            4 => vec![Transfer::new(Phase::Decode,fde::decode(cpu.cir().ok_or(Fault::EmptyCir)?))],
+            _ => {
+                let (transfers,mar) = fde::execute(cpu.cir().ok_or(Fault::EmptyCir)?,cpu.ix(),cpu.memory.cells());
+                cpu.execute_cir()?;
+                if let Some(Instruction::STO(addr)) = cpu.cir() {
+                    self.stored = Some(*addr);
+                }
+                if let Some(mar) = mar {
+                    cpu.mar = mar;
+                    cpu.mdr = Word::Data(cpu.memory.get(mar as usize).unwrap_or(0));
+                }
+                transfers.into_iter().map(|t| Transfer::new(Phase::Execute,t)).collect()
+            },
+        };
+        Ok(transfers)
+    }
+    /// Whether [`Machine::micro_step`] has fetched an instruction that is yet to be executed
+    pub fn mid_cycle(&self) -> bool {
+        self.stage != 0
+    }
+    /// Steps until the program reaches `END`, returning the number of steps taken in total
+    pub fn run(&mut self) -> Result<usize,Fault> {
+        while !self.done() {
+            self.step()?;
+        }
+        Ok(self.steps())
+    }
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+    /// The instruction that will be executed by the next step and the source line it came from
+    pub fn current(&self) -> Option<(&Instruction,&DebugInfo)> {
+        self.program.get(self.pc() as usize)
+    }
+    /// The source line of the instruction being executed, or of the last one executed. After a
+    /// fault this is the instruction it came from, even though the PC may have moved past it or
+    /// jumped to an address with no instruction
+    pub fn executing(&self) -> Option<&DebugInfo> {
+        self.program.get(self.executing? as usize).map(|(_,info)| info)
+    }
+    /// Where [`Machine::executing`] is, for reporting a fault
+    pub fn location(&self) -> String {
+        self.executing().map_or(String::from("the start of the program"),|info| info.location())
+    }
+    pub fn steps(&self) -> usize {
+        self.watchdog.steps
+    }
+    pub fn done(&self) -> bool {
+        self.computer.cpu.done()
+    }
+    pub fn pc(&self) -> u16 {
+        self.computer.cpu.pc()
+    }
+    pub fn acc(&self) -> i16 {
+        self.computer.cpu.acc()
+    }
+    pub fn ix(&self) -> i16 {
+        self.computer.cpu.ix()
+    }
+    pub fn flag(&self, flag : FLAGS) -> bool {
+        self.computer.cpu.get_flag(flag)
+    }
+    pub fn memory(&self) -> &Memory<MEMORY_SIZE> {
+        &self.computer.cpu.memory
+    }
+    /// Changes how the registers and memory are shown when the machine is displayed
+    pub fn set_style(&mut self, style : DisplayStyle) {
+        self.computer.set_display_style(style);
+    }
+    /// The address the last instruction stored to, if it was a `STO`
+    pub fn stored(&self) -> Option<u16> {
+        self.stored
+    }
+    pub fn output(&self) -> &O {
+        self.computer.cpu.output()
+    }
+    pub fn cpu(&self) -> &CPU<I,O,MEMORY_SIZE> {
+        &self.computer.cpu
+    }
+}
+impl<I : Read,O : Write> Display for Machine<I,O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.computer.fmt(f)
+    }
+}
+
+/// Why there is no instruction for the PC to fetch
+fn missing(program : &Program, pc : u16) -> Fault {
+    if program.is_gap(pc as usize) {
+        Fault::NoInstruction(pc)
+    } else {
+        Fault::RanOffEnd(pc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `src` to the end with `input` for IN, giving the steps taken or why it stopped
+    fn run(src : &str, config : Config, input : &str) -> Result<usize,Fault> {
+        let program = crate::assemble(src).unwrap_or_else(|e| panic!("{}",e));
+        Machine::with_io(program,config,input.as_bytes(),Vec::new()).run()
+    }
+
+    #[test]
+    fn tight_loop_is_detected() {
+        let fault = run("LDM #1\nLOOP: JMP LOOP\nEND",Config::default(),"");
+        assert!(matches!(fault,Err(Fault::InfiniteLoop {line : 1,from : 1,to : 1})),"{:?}",fault);
+    }
+
+    #[test]
+    fn loop_over_several_lines_is_detected() {
+        let fault = run("TOP: LDM #5\nSTO X\nJMP TOP\nEND",Config::default(),"");
+        assert!(matches!(fault,Err(Fault::InfiniteLoop {from : 0,to : 2,..})),"{:?}",fault);
+    }
+
+    #[test]
+    fn counting_loop_is_not_a_loop() {
+        let src = "LDM #0\nLOOP: INC ACC\nCMP #30000\nJPN LOOP\nEND";
+        let config = Config {max_steps : 0, ..Config::default()};
+        assert_eq!(run(src,config,"").ok(),Some(1 + 3 * 30000 + 1));
+    }
+
+    #[test]
+    fn input_starts_the_search_again() {
+        //the state repeats, but different input could be read each time round
+        let fault = run("LOOP: IN\nJMP LOOP\nEND",Config::default(),"a\na\na\n");
+        assert!(matches!(fault,Err(Fault::NoInput)),"{:?}",fault);
+    }
+
+    #[test]
+    fn step_limit_without_loop_detection() {
+        let config = Config {max_steps : 50, detect_loops : false, ..Config::default()};
+        let fault = run("LOOP: JMP LOOP\nEND",config,"");
+        assert!(matches!(fault,Err(Fault::StepLimit(50))),"{:?}",fault);
+    }
+}
@@ -0,0 +1,306 @@
+//! Macros, named blocks of lines with parameters that are copied in wherever they are used.
+//!
+//! ```text
+//! MACRO SWAP A, B
+//!     LDD A
+//!     STO TEMP
+//!     LDD B
+//!     STO A
+//!     LDD TEMP
+//!     STO B
+//! ENDM
+//!     SWAP X, Y
+//! ```
+//!
+//! Labels defined inside a macro are renamed every time it is expanded, so that a macro with a
+//! loop in it can be used more than once.
+use std::{collections::HashMap,ops::Range,path::PathBuf};
+use super::{docs,include::SourceLine,source::{self,Line},Diagnostic};
+
+/// How deep macros can call other macros
+const MAX_DEPTH : usize = 16;
+/// How many lines macros can expand to in all, which is more than fits in memory
+const MAX_LINES : usize = 1 << 15;
+
+struct Macro {
+    params : Vec<String>,
+    body : Vec<String>,
+    /// The labels defined in the body
+    labels : Vec<String>,
+}
+
+/// A line of a program once its macros have been expanded
+pub struct Expanded {
+    /// The included file it came from, None for the program itself
+    pub file : Option<PathBuf>,
+    /// The source line it came from, which is the call for a line from a macro
+    pub line : usize,
+    pub text : String,
+    /// The macro call it came from
+    pub call : Option<String>,
+}
+
+struct Expander {
+    macros : HashMap<String,Macro>,
+    /// Expansions so far, which makes each one's labels unique
+    count : usize,
+    lines : Vec<Expanded>,
+    errors : Vec<Diagnostic>,
+    /// Lines copied out of macro bodies so far
+    expanded : usize,
+    /// Set once the line being expanded has gone too deep or too long, so that the calls still
+    /// waiting to be expanded don't report it again
+    stopped : bool,
+}
+
+/// Takes out every macro definition and replaces every call with the lines of the macro
+pub fn expand(src : Vec<SourceLine>) -> (Vec<Expanded>,Vec<Diagnostic>) {
+    let mut expander = Expander {macros : HashMap::new(), count : 0, lines : Vec::new(), errors : Vec::new(), expanded : 0, stopped : false};
+    let mut lines = src.into_iter();
+    while let Some(source) = lines.next() {
+        let error = |line : usize, message : String| Diagnostic::error(line,message).in_file(source.file.clone());
+        let (keyword,rest) = first_word(Line::parse(&source.text).code);
+        match keyword.to_ascii_uppercase().as_str() {
+            "MACRO" => {
+                let mut body = Vec::new();
+                let mut closed = false;
+                for body_line in lines.by_ref() {
+                    match first_word(Line::parse(&body_line.text).code).0.to_ascii_uppercase().as_str() {
+                        "ENDM" => {
+                            closed = true;
+                            break
+                        },
+                        "MACRO" => expander.errors.push(error(body_line.line,String::from("A macro can't be defined inside another one"))),
+                        _ => body.push(body_line.text),
+                    }
+                }
+                if !closed {
+                    expander.errors.push(error(source.line,String::from("MACRO has no ENDM to finish it")));
+                }
+                match define(rest,body) {
+                    Ok((name,m)) => if expander.macros.insert(name.to_ascii_uppercase(),m).is_some() {
+                        expander.errors.push(error(source.line,format!("The macro '{}' is defined more than once",name)));
+                    },
+                    Err(message) => expander.errors.push(error(source.line,message)),
+                }
+            },
+            "ENDM" => expander.errors.push(error(source.line,String::from("ENDM without a MACRO before it"))),
+            _ => {
+                expander.stopped = false;
+                expander.line(&source,&source.text,None,0);
+            },
+        }
+    }
+    (expander.lines,expander.errors)
+}
+
+impl Expander {
+    /// Adds a line, expanding it if it calls a macro
+    fn line(&mut self, source : &SourceLine, text : &str, call : Option<&str>, depth : usize) {
+        if self.stopped {
+            return
+        }
+        let error = |message : String| Diagnostic::error(source.line,message).in_file(source.file.clone());
+        let line = Line::parse(text);
+        let (name,args) = first_word(line.code);
+        let m = match self.macros.get(&name.to_ascii_uppercase()) {
+            Some(m) if !line.is_data() => m,
+            _ => {
+                self.lines.push(Expanded {
+                    file : source.file.clone(),
+                    line : source.line,
+                    text : text.to_string(),
+                    call : call.map(String::from),
+                });
+                return
+            },
+        };
+        //a label on the call is kept, on its own line before what the macro expands to
+        if let Some(label) = line.label {
+            self.lines.push(Expanded {
+                file : source.file.clone(),
+                line : source.line,
+                text : format!("{}:",label),
+                call : call.map(String::from),
+            });
+        }
+        if depth == MAX_DEPTH {
+            self.errors.push(error(format!("Macros call each other more than {} deep",MAX_DEPTH)));
+            self.stopped = true;
+            return
+        }
+        let args = if args.is_empty() {Vec::new()} else {source::comma_separated(args)};
+        if args.len() != m.params.len() {
+            self.errors.push(error(
+                format!("The macro '{}' takes {} argument{} but was given {}",name,m.params.len(),
+                    if m.params.len() == 1 {""} else {"s"},args.len())));
+            return
+        }
+        if self.expanded + m.body.len() > MAX_LINES {
+            self.errors.push(error(format!("Macros expand to more than {} lines",MAX_LINES)));
+            self.stopped = true;
+            return
+        }
+        self.expanded += m.body.len();
+        self.count += 1;
+        let body : Vec<String> = m.body.iter().map(|text| substitute(text,m,&args,self.count)).collect();
+        //a call inside a macro is shown as the call in the program that led to it
+        let call = call.unwrap_or(line.code);
+        for text in body {
+            self.line(source,&text,Some(call),depth + 1);
+        }
+    }
+}
+
+fn first_word(code : &str) -> (&str,&str) {
+    match code.split_once(char::is_whitespace) {
+        Some((word,rest)) => (word,rest.trim()),
+        None              => (code,""),
+    }
+}
+
+/// Reads the name and parameters after `MACRO`
+fn define(header : &str, body : Vec<String>) -> Result<(String,Macro),String> {
+    let (name,params) = first_word(header);
+    if !source::is_name(name) {
+        return Err(String::from("MACRO needs a name, like 'MACRO SWAP A, B'"));
+    }
+    if docs::mnemonic(name).is_some() {
+        return Err(format!("'{}' is an instruction so can't be the name of a macro",name));
+    }
+    let params : Vec<String> = if params.is_empty() {Vec::new()} else {
+        source::comma_separated(params).into_iter().map(String::from).collect()
+    };
+    for (i,param) in params.iter().enumerate() {
+        if !source::is_name(param) {
+            return Err(format!("'{}' can't be the name of a parameter",param));
+        }
+        if params[..i].contains(param) {
+            return Err(format!("The parameter '{}' is named more than once",param));
+        }
+    }
+    let labels = body.iter().filter_map(|text| Line::parse(text).label.map(String::from)).collect();
+    Ok((name.to_string(),Macro {params,body,labels}))
+}
+
+/// A line of a macro with the parameters replaced by the arguments and its labels made unique
+fn substitute(text : &str, m : &Macro, args : &[&str], count : usize) -> String {
+    let spans = source::spans(text);
+    let mut replacements : Vec<(Range<usize>,String)> = Vec::new();
+    let mut names = spans.label.into_iter().collect::<Vec<_>>();
+    if let Some(operand) = spans.operand {
+        names.extend(source::identifiers(&text[operand.clone()]).into_iter()
+            .map(|r| operand.start + r.start..operand.start + r.end));
+    }
+    for r in names {
+        let name = &text[r.clone()];
+        if let Some(i) = m.params.iter().position(|p| p == name) {
+            replacements.push((r,args[i].to_string()));
+        } else if m.labels.iter().any(|l| l == name) {
+            replacements.push((r,format!("{}__{}",name,count)));
+        }
+    }
+    let mut text = text.to_string();
+    for (r,replacement) in replacements.into_iter().rev() {
+        text.replace_range(r,&replacement);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src : &str) -> (Vec<String>,Vec<String>) {
+        let lines = src.lines().enumerate()
+            .map(|(line,text)| SourceLine {file : None, line, text : text.to_string()})
+            .collect();
+        let (lines,errors) = expand(lines);
+        (lines.into_iter().map(|l| l.text.trim().to_string()).collect(),errors.into_iter().map(|e| e.message).collect())
+    }
+
+    #[test]
+    fn substitutes_arguments() {
+        let (lines,errors) = run("MACRO SWAP A, B\nLDD A\nSTO TEMP\nLDD B\nSTO A\nENDM\nSWAP X, Y\nEND");
+        assert!(errors.is_empty());
+        assert_eq!(lines,["LDD X","STO TEMP","LDD Y","STO X","END"]);
+    }
+
+    #[test]
+    fn argument_count_mismatch() {
+        let (lines,errors) = run("MACRO ADD2 A, B\nLDD A\nADD B\nENDM\nADD2 X\nEND");
+        assert_eq!(errors,["The macro 'ADD2' takes 2 arguments but was given 1"]);
+        assert_eq!(lines,["END"]);
+        let (_,errors) = run("MACRO ONE A\nLDD A\nENDM\nONE X, Y");
+        assert_eq!(errors,["The macro 'ONE' takes 1 argument but was given 2"]);
+    }
+
+    #[test]
+    fn labels_renamed_per_expansion() {
+        let (lines,errors) = run("MACRO WAIT\nLOOP: DEC ACC\nJPN LOOP\nENDM\nWAIT\nWAIT");
+        assert!(errors.is_empty());
+        assert_eq!(lines,["LOOP__1: DEC ACC","JPN LOOP__1","LOOP__2: DEC ACC","JPN LOOP__2"]);
+    }
+
+    #[test]
+    fn label_on_call_is_kept() {
+        let (lines,_) = run("MACRO NOTHING\nEND\nENDM\nSTART: NOTHING");
+        assert_eq!(lines,["START:","END"]);
+    }
+
+    #[test]
+    fn recursion_stops_at_max_depth() {
+        let (lines,errors) = run("MACRO FOREVER\nINC ACC\nFOREVER\nENDM\nFOREVER");
+        assert_eq!(errors,[format!("Macros call each other more than {} deep",MAX_DEPTH)]);
+        assert_eq!(lines.len(),MAX_DEPTH);
+    }
+
+    #[test]
+    fn recursion_fanning_out_stops_at_max_depth() {
+        //each call makes four more, so this would run to 4^16 calls if it kept going
+        let (lines,errors) = run("MACRO R\nR\nR\nR\nR\nENDM\nR\nEND");
+        assert_eq!(errors,[format!("Macros call each other more than {} deep",MAX_DEPTH)]);
+        assert_eq!(lines,["END"]);
+    }
+
+    #[test]
+    fn expansion_stops_at_max_lines() {
+        //no macro calls itself, but each level calls the one below four times
+        let mut src = String::from("MACRO M0\nINC ACC\nENDM\n");
+        for level in 1..9 {
+            src += &format!("MACRO M{}\n{}ENDM\n",level,format!("M{}\n",level - 1).repeat(4));
+        }
+        let (lines,errors) = run(&(src + "M8\nM1\nEND"));
+        //once the lines run out, later calls can't be expanded either
+        let error = format!("Macros expand to more than {} lines",MAX_LINES);
+        assert_eq!(errors,[error.clone(),error]);
+        assert!(lines.len() <= MAX_LINES);
+        assert_eq!(lines.last().map(String::as_str),Some("END"));
+    }
+
+    #[test]
+    fn missing_endm() {
+        let (_,errors) = run("MACRO OPEN A\nLDD A\nEND");
+        assert_eq!(errors,["MACRO has no ENDM to finish it"]);
+        let (_,errors) = run("LDD X\nENDM");
+        assert_eq!(errors,["ENDM without a MACRO before it"]);
+    }
+
+    #[test]
+    fn macros_calling_macros() {
+        let src = "MACRO COPY FROM, TO\nLDD FROM\nSTO TO\nENDM\nMACRO SWAP A, B\nCOPY A, TEMP\nCOPY B, A\nCOPY TEMP, B\nENDM\nSWAP X, Y";
+        let (lines,errors) = run(src);
+        assert!(errors.is_empty());
+        assert_eq!(lines,["LDD X","STO TEMP","LDD Y","STO X","LDD TEMP","STO Y"]);
+    }
+
+    #[test]
+    fn bad_definitions() {
+        let (_,errors) = run("MACRO LDD A\nENDM");
+        assert_eq!(errors,["'LDD' is an instruction so can't be the name of a macro"]);
+        let (_,errors) = run("MACRO TWICE A, A\nENDM");
+        assert_eq!(errors,["The parameter 'A' is named more than once"]);
+        let (_,errors) = run("MACRO M\nENDM\nMACRO m\nENDM");
+        assert_eq!(errors,["The macro 'm' is defined more than once"]);
+    }
+}
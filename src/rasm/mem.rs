@@ -1,30 +1,46 @@
-use crate::{DisplayStyle};
-use std::{fmt::Display, ops::{Index,IndexMut}};
+use crate::{DisplayStyle,SymbolTable};
+use std::{fmt::Display, ops::{Index,IndexMut,Range}};
 
 
 //variables go in first couple slots
 pub struct Memory<const N : usize> {
-    min_addr : u16,
     mem : [i16; N],
     
     disp_style : DisplayStyle,
-    num_vars: usize,
+    /// The addresses shown, see [`SymbolTable::addresses`]
+    addresses : Vec<usize>,
 }
 
 impl<const N : usize> Memory<N> {
-    pub fn new(min_addr : u16,disp_style : DisplayStyle, num_vars: usize) -> Self {
+    pub fn new(addresses : Vec<usize>,disp_style : DisplayStyle) -> Self {
         Memory {
-            min_addr,
             mem : [0;N],
             disp_style,
-            num_vars
+            addresses,
         }
     }
-    pub fn min_addr(&self) -> u16 {
-        self.min_addr
+    pub fn set_display_style(&mut self, style : DisplayStyle) {
+        self.disp_style = style;
     }
-    pub fn max_addr(&self) -> u16 {
-        self.num_vars as u16
+    pub fn addresses(&self) -> &[usize] {
+        &self.addresses
+    }
+    pub fn cells(&self) -> &[i16] {
+        &self.mem
+    }
+    pub fn get(&self, addr : usize) -> Option<i16> {
+        self.mem.get(addr).copied()
+    }
+    pub fn get_mut(&mut self, addr : usize) -> Option<&mut i16> {
+        self.mem.get_mut(addr)
+    }
+    /// Sets the cells a program declared with initial values
+    pub fn load(&mut self, data : &[(u16,i16)]) {
+        for &(addr,value) in data {
+            if let Some(cell) = self.mem.get_mut(addr as usize) {
+                *cell = value;
+            }
+        }
     }
 }
 
@@ -42,7 +58,7 @@ impl<const N:usize> IndexMut<usize> for Memory<N> {
 impl<const N: usize> Display for Memory<N> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let max_len : u16 = match self.disp_style {
-            DisplayStyle::Denary =>  {
+            DisplayStyle::Denary | DisplayStyle::All =>  {
                 5
             },
             DisplayStyle::Binary => {
@@ -58,18 +74,18 @@ impl<const N: usize> Display for Memory<N> {
       writeln!(f,"{:^width$}|","Contents",width=max_len as usize)?;
       writeln!(f," {:-^width$}",'-',width = (max_len * 2 + 1) as usize)?;
       writeln!(f," {:-^width$}",'-',width = (max_len * 2 + 1) as usize)?;
-      for addr in self.min_addr()..self.max_addr() {
+      for &addr in self.addresses.iter().filter(|&&addr| addr < N) {
         
         match self.disp_style {
-            DisplayStyle::Denary => {
-                writeln!(f,"|{:^width$}|{:^width$}|",addr,self[addr as usize],width=max_len as usize)?;
+            DisplayStyle::Denary | DisplayStyle::All => {
+                writeln!(f,"|{:^width$}|{:^width$}|",addr,self[addr],width=max_len as usize)?;
                 
             },
             DisplayStyle::Binary => {   
-                writeln!(f,"|{:^#width$b}|{:^#width$b}|",addr,self[addr as usize],width=max_len as usize)?;
+                writeln!(f,"|{:^#width$b}|{:^#width$b}|",addr,self[addr],width=max_len as usize)?;
             },
             DisplayStyle::Hex   => {
-                writeln!(f,"|{:^#width$x}|{:^#width$x}|",addr,self[addr as usize],width=max_len as usize)?;
+                writeln!(f,"|{:^#width$x}|{:^#width$x}|",addr,self[addr],width=max_len as usize)?;
             }
         }
       }
@@ -78,3 +94,77 @@ impl<const N: usize> Display for Memory<N> {
 
     }
 }
+
+/// A table of memory cells with the variable at each address, as shown while stepping
+pub struct View<'a> {
+    cells : &'a [i16],
+    table : &'a SymbolTable,
+    style : DisplayStyle,
+    addresses : Vec<usize>,
+    changed : Option<usize>,
+    highlight : bool,
+}
+impl<'a> View<'a> {
+    /// Shows the addresses the program uses, see [`SymbolTable::addresses`]
+    pub fn new<const N : usize>(memory : &'a Memory<N>, table : &'a SymbolTable, style : DisplayStyle) -> Self {
+        Self {
+            cells : memory.cells(),
+            table,
+            style,
+            addresses : table.addresses(),
+            changed : None,
+            highlight : false,
+        }
+    }
+    pub fn range(mut self, range : Range<usize>) -> Self {
+        self.addresses = range.collect();
+        self
+    }
+    /// Marks the cell the last instruction changed
+    pub fn changed(mut self, addr : Option<usize>) -> Self {
+        self.changed = addr;
+        self
+    }
+    /// Shows the changed cell in reverse video as well as marking it, for terminals
+    pub fn highlight(mut self, highlight : bool) -> Self {
+        self.highlight = highlight;
+        self
+    }
+}
+impl Display for View<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names : Vec<Vec<&str>> = vec![Vec::new();self.cells.len()];
+        for (name,addr) in self.table.vars() {
+            if let Some(n) = names.get_mut(addr) {
+                n.push(name);
+            }
+        }
+        let rows : Vec<(usize,String,String)> = self.addresses.iter().copied()
+            .filter(|&addr| addr < self.cells.len())
+            .map(|addr| {
+                names[addr].sort_unstable();
+                let value = match names[addr].first() {
+                    Some(name) => self.table.format_value(name,self.cells[addr],self.style),
+                    None       => self.style.format(self.cells[addr]),
+                };
+                (addr,names[addr].join(", "),value)
+            })
+            .collect();
+        let name_width = rows.iter().map(|(_,name,_)| name.len()).max().unwrap_or(0).max(4);
+        let header = match self.style {
+            DisplayStyle::All => DisplayStyle::ALL_COLUMNS,
+            _                 => "VALUE",
+        };
+        let value_width = rows.iter().map(|(_,_,value)| value.len()).max().unwrap_or(0).max(header.len());
+        writeln!(f," ADDR  {:<nw$}  {:>vw$}","NAME",header,nw = name_width,vw = value_width)?;
+        for (addr,name,value) in rows {
+            let row = format!(" {:>4}  {:<nw$}  {:>vw$}",addr,name,value,nw = name_width,vw = value_width);
+            match self.changed {
+                Some(changed) if changed == addr && self.highlight => writeln!(f,"\x1b[7m{}\x1b[0m  <- changed",row)?,
+                Some(changed) if changed == addr => writeln!(f,"{}  <- changed",row)?,
+                _ => writeln!(f,"{}",row)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! The machine code form of a program, as written by `rasm-cli assemble`.
+//!
+//! Every instruction is two 16 bit words: the opcode in the high byte of the first word
+//! (with the low byte set to 1 when the operand is an immediate value) and then the operand.
+//! A file is the bytes `RASM`, the version of the layout, the number of instructions, then the
+//! instructions, followed by the number of declared memory cells and an address and value for
+//! each, all big endian.
+use super::{Code,Instruction,Register};
+
+const MAGIC : &[u8;4] = b"RASM";
+/// Changes whenever the layout does, older files have to be assembled again
+const VERSION : u16 = 1;
+
+/// Opcodes are the position in this table plus one, so that 0 is never a valid instruction
+const OPCODES : [&str;22] = [
+    "LDM","LDD","LDI","LDX","LDR","MOV","STO","ADD","SUB","INC","DEC",
+    "JMP","CMP","JPE","JPN","IN","OUT","END","XOR","OR","LSL","LSR",
+];
+
+pub fn encode(instr : &Instruction) -> [u16;2] {
+    let op = OPCODES.iter().position(|&m| m == instr.mnemonic()).unwrap() as u16 + 1;
+    match instr.operand() {
+        Some((v,imm)) => [op << 8 | imm as u16,v],
+        None          => [op << 8,0],
+    }
+}
+
+pub fn decode(words : [u16;2]) -> Option<Instruction> {
+    let mnemonic = *OPCODES.get(((words[0] >> 8) as usize).checked_sub(1)?)?;
+    match (mnemonic,words[0] & 0xff) {
+        ("IN",0)  => Some(Instruction::IO(true)),
+        ("OUT",0) => Some(Instruction::IO(false)),
+        ("END",0) => Some(Instruction::END),
+        ("INC" | "DEC" | "MOV",0) => Instruction::with_register(mnemonic,Register::from_code(words[1])?),
+        (_,0)     => Instruction::new(mnemonic,words[1]),
+        (_,1)     => Instruction::with_imm(mnemonic,words[1]),
+        _         => None,
+    }
+}
+
+pub fn write(code : &Code) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+    bytes.extend_from_slice(&(code.code.len() as u16).to_be_bytes());
+    for instr in &code.code {
+        for word in encode(instr).iter() {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+    }
+    bytes.extend_from_slice(&(code.data.len() as u16).to_be_bytes());
+    for &(addr,value) in &code.data {
+        bytes.extend_from_slice(&addr.to_be_bytes());
+        bytes.extend_from_slice(&value.to_be_bytes());
+    }
+    bytes
+}
+
+/// What an object file holds
+pub struct Object {
+    pub code : Vec<Instruction>,
+    /// The initial value of every declared memory cell, by address
+    pub data : Vec<(u16,i16)>,
+}
+
+pub fn read(bytes : &[u8]) -> Result<Object,String> {
+    let rest = bytes.strip_prefix(&MAGIC[..]).ok_or("not a rasm object file")?;
+    let word = |i : usize| rest.get(i * 2..i * 2 + 2).map(|b| u16::from_be_bytes([b[0],b[1]]));
+    match word(0).ok_or("object file is truncated")? {
+        VERSION => (),
+        version => return Err(format!("object file is version {} but only version {} can be read, assemble it again",version,VERSION)),
+    }
+    let count = word(1).ok_or("object file is truncated")? as usize;
+    let code = (0..count).map(|i| {
+        let words = [word(2 + i * 2),word(3 + i * 2)];
+        match words {
+            [Some(a),Some(b)] => decode([a,b]).ok_or(format!("instruction {} is not valid",i)),
+            _ => Err("object file is truncated".to_string()),
+        }
+    }).collect::<Result<_,_>>()?;
+    let start = 2 + count * 2;
+    let cells = word(start).ok_or("object file is truncated")? as usize;
+    let data = (0..cells).map(|i| match (word(start + 1 + i * 2),word(start + 2 + i * 2)) {
+        (Some(addr),Some(value)) => Ok((addr,value as i16)),
+        _ => Err("object file is truncated".to_string()),
+    }).collect::<Result<_,_>>()?;
+    let end = (start + 1 + cells * 2) * 2;
+    if rest.len() > end {
+        return Err(format!("object file has {} bytes after the end of the program",rest.len() - end));
+    }
+    Ok(Object {code,data})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC : &str = "LDM #5\nLDR #ARR\nINC IX\nOUT\nSTO X\nEND\nARR: #1,#-2\nX: 'A'";
+
+    fn object() -> Vec<u8> {
+        write(&crate::assemble(SRC).unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = crate::assemble(SRC).unwrap();
+        let object = read(&write(&program)).unwrap();
+        let words = |code : &[Instruction]| code.iter().map(encode).collect::<Vec<_>>();
+        assert_eq!(words(&object.code),words(&program.code));
+        assert_eq!(object.data,program.data);
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let mut bytes = object();
+        bytes[0] = b'X';
+        assert_eq!(read(&bytes).err().as_deref(),Some("not a rasm object file"));
+    }
+
+    #[test]
+    fn other_version() {
+        let mut bytes = object();
+        bytes[5] = 0;
+        assert!(read(&bytes).err().unwrap().starts_with("object file is version 0"));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = object();
+        for len in 4..bytes.len() {
+            assert_eq!(read(&bytes[..len]).err().as_deref(),Some("object file is truncated"),"{} bytes",len);
+        }
+    }
+
+    #[test]
+    fn left_over_bytes() {
+        let mut bytes = object();
+        bytes.extend_from_slice(&[0,0]);
+        assert_eq!(read(&bytes).err().as_deref(),Some("object file has 2 bytes after the end of the program"));
+    }
+}
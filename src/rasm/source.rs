@@ -0,0 +1,219 @@
+//! Splitting a line of source into its label, code and comment.
+use std::ops::Range;
+use super::literal;
+
+/// One line of a program, every part trimmed
+pub struct Line<'a> {
+    pub label : Option<&'a str>,
+    pub code : &'a str,
+    /// Includes the leading ';'
+    pub comment : Option<&'a str>,
+}
+impl<'a> Line<'a> {
+    pub fn parse(line : &'a str) -> Self {
+        let (code,comment) = split_comment(line);
+        let code = code.trim();
+        if let Some(label) = code.strip_suffix(':') {
+            return Line {label : Some(label.trim()), code : "", comment};
+        }
+        match labelled(code) {
+            Some((label,code)) => Line {label : Some(label), code, comment},
+            None               => Line {label : None, code, comment},
+        }
+    }
+    pub fn is_blank(&self) -> bool {
+        self.label.is_none() && self.code.is_empty() && self.comment.is_none()
+    }
+    /// Whether this line declares data, like `X: #5`, rather than an instruction
+    pub fn is_data(&self) -> bool {
+        self.label.is_some() && is_data(self.code)
+    }
+}
+
+/// Whether `s` can name a label, variable or constant
+pub fn is_name(s : &str) -> bool {
+    s.starts_with(|c : char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_data(code : &str) -> bool {
+    code.starts_with(['#','\'','"'])
+}
+
+/// Splits a label from what follows it on the same line, an instruction like `LOOP: LDD X` or
+/// the values of a data declaration like `X: #5`
+fn labelled(code : &str) -> Option<(&str,&str)> {
+    let (label,rest) = code.split_once(':')?;
+    let (label,rest) = (label.trim_end(),rest.trim());
+    //the label is one word, whether or not it is a good name, so that a bad one can be reported
+    let word = !label.is_empty() && !label.contains(|c : char| c.is_whitespace() || c == '\'' || c == '"');
+    if word && !rest.is_empty() {
+        Some((label,rest))
+    } else {
+        None
+    }
+}
+
+/// Splits a constant definition, `NAME EQU value` or `.const NAME value`, into its name and value
+pub fn constant(code : &str) -> Option<(&str,&str)> {
+    let (first,rest) = code.split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+    if first.eq_ignore_ascii_case(".const") {
+        let (name,value) = rest.split_once(char::is_whitespace)?;
+        return Some((name,value.trim()));
+    }
+    let (keyword,value) = rest.split_once(char::is_whitespace)?;
+    if keyword.eq_ignore_ascii_case("EQU") {
+        Some((first,value.trim()))
+    } else {
+        None
+    }
+}
+
+/// The operand of an `ORG` line, which is empty if it is missing
+pub fn org(code : &str) -> Option<&str> {
+    let (keyword,rest) = code.split_once(char::is_whitespace).unwrap_or((code,""));
+    if keyword.eq_ignore_ascii_case("ORG") {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+/// The characters of `text` that aren't inside a character or string literal, with where they are
+pub fn unquoted(text : &str) -> impl Iterator<Item = (usize,char)> + '_ {
+    let mut skip_to = 0;
+    text.char_indices().filter(move |&(i,c)| {
+        if i < skip_to {
+            return false;
+        }
+        if c == '\'' || c == '"' {
+            //a literal that isn't closed runs to the end
+            skip_to = i + literal::quoted_len(&text[i..]).unwrap_or(text.len() - i);
+            return false;
+        }
+        true
+    })
+}
+
+/// Splits a list, like data values or macro arguments, at the commas that aren't inside a
+/// character or string literal
+pub fn comma_separated(list : &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    for (i,_) in unquoted(list).filter(|&(_,c)| c == ',') {
+        items.push(list[start..i].trim());
+        start = i + 1;
+    }
+    items.push(list[start..].trim());
+    items
+}
+
+/// Splits at the first ';' that isn't inside a character or string literal
+fn split_comment(line : &str) -> (&str,Option<&str>) {
+    match unquoted(line).find(|&(_,c)| c == ';') {
+        Some((i,_)) => (&line[..i],Some(line[i..].trim_end())),
+        None        => (line,None),
+    }
+}
+
+/// Where the parts of a line are, as byte ranges into it
+#[derive(Default)]
+pub struct Spans {
+    pub label : Option<Range<usize>>,
+    pub mnemonic : Option<Range<usize>>,
+    pub operand : Option<Range<usize>>,
+    pub comment : Option<Range<usize>>,
+}
+
+pub fn spans(line : &str) -> Spans {
+    let (code,comment) = split_comment(line);
+    let mut spans = Spans {
+        comment : comment.map(|c| code.len()..code.len() + c.len()),
+        ..Spans::default()
+    };
+    let mut start = code.len() - code.trim_start().len();
+    let mut code = code.trim();
+    if let Some(label) = code.strip_suffix(':') {
+        spans.label = Some(start..start + label.trim_end().len());
+        return spans;
+    }
+    if let Some((label,rest)) = labelled(code) {
+        //the rest of the line is split up as if the label wasn't there
+        spans.label = Some(start..start + label.len());
+        start += rest.as_ptr() as usize - code.as_ptr() as usize;
+        code = rest;
+        if is_data(code) {
+            spans.operand = Some(start..start + code.len());
+            return spans;
+        }
+    }
+    if let Some((name,value)) = constant(code) {
+        //the name is the definition and EQU or .const stands in for the mnemonic
+        let at = |part : &str| start + (part.as_ptr() as usize - code.as_ptr() as usize);
+        let keyword = if code.starts_with('.') {&code[..".const".len()]} else {&code[name.len()..].trim_start()[..3]};
+        spans.label = Some(at(name)..at(name) + name.len());
+        spans.mnemonic = Some(at(keyword)..at(keyword) + keyword.len());
+        spans.operand = Some(at(value)..at(value) + value.len());
+        return spans;
+    }
+    let mnemonic_len = code.find(|c : char| !c.is_alphabetic()).unwrap_or(code.len());
+    if mnemonic_len > 0 {
+        spans.mnemonic = Some(start..start + mnemonic_len);
+    }
+    let operand = code[mnemonic_len..].trim_start();
+    if !operand.is_empty() {
+        let operand_start = start + code.len() - operand.len();
+        spans.operand = Some(operand_start..operand_start + operand.len());
+    }
+    spans
+}
+
+/// The names used in an operand, skipping numbers, literals and the letters of `#B`/`#&` prefixes.
+/// A name after `#` is a constant
+pub fn identifiers(operand : &str) -> Vec<Range<usize>> {
+    let bytes = operand.as_bytes();
+    let mut names = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'\'' || c == b'"' {
+            i += literal::quoted_len(&operand[i..]).unwrap_or(bytes.len() - i);
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            //#B101 and &FF are numbers but #NAME is a constant, and a whole operand B101 is a variable
+            let number = literal::is_number(&operand[start..i]) && start > 0;
+            let hex = start > 0 && bytes[start - 1] == b'&';
+            if !number && !hex && !c.is_ascii_digit() {
+                names.push(start..i);
+            }
+            continue;
+        }
+        i += 1;
+    }
+    names
+}
+
+/// Everywhere `name` is defined as a label or used as an operand on this line
+pub fn references(line : &str, name : &str) -> Vec<Range<usize>> {
+    let spans = spans(line);
+    let mut found = Vec::new();
+    if let Some(label) = spans.label {
+        if &line[label.clone()] == name {
+            found.push(label);
+        }
+    }
+    if let Some(operand) = spans.operand {
+        for r in identifiers(&line[operand.clone()]) {
+            if &line[operand.start + r.start..operand.start + r.end] == name {
+                found.push(operand.start + r.start..operand.start + r.end);
+            }
+        }
+    }
+    found
+}
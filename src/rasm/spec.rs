@@ -0,0 +1,182 @@
+//! Test specs describe what a program should do so that it can be checked without stepping through it.
+//!
+//! ```text
+//! ; comments start with ';' like in a program
+//! [picks the larger number]
+//! input  = 57     ; each character is given to one IN instruction
+//! output = 7      ; everything the program OUTs, \n is a newline
+//! Y      = 7      ; a variable or ACC/IX must hold this value at END
+//! ```
+//!
+//! Input and output can go in double quotes to keep spaces at either end or a `;`, with `\"`
+//! for a quote, like `output = "A; B "`. Values are numbers as written in a program, so
+//! `X = 65535`, `X = -1` and `X = &FFFF` all expect the same word.
+use super::{Code,Machine,Config,cpu::Fault,literal,machine::WORD_BITS};
+
+pub struct TestCase {
+    pub name : String,
+    pub input : String,
+    pub output : Option<String>,
+    pub checks : Vec<(String,i32)>,
+}
+
+pub struct Spec {
+    pub cases : Vec<TestCase>,
+}
+
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Faulted(Fault),
+}
+
+impl Spec {
+    pub fn parse(src : &str) -> Result<Self,String> {
+        let mut cases : Vec<TestCase> = Vec::new();
+        for (index,line) in src.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                cases.push(TestCase {
+                    name : name.trim().to_string(),
+                    input : String::new(),
+                    output : None,
+                    checks : Vec::new(),
+                });
+                continue;
+            }
+            let err = |msg : &str| format!("line {}: {} ({})",index + 1,msg,line);
+            let case = cases.last_mut().ok_or_else(|| err("expected a [test name] first"))?;
+            let (key,value) = line.split_once('=').ok_or_else(|| err("expected 'key = value'"))?;
+            let (key,value) = (key.trim(),value.trim());
+            match key {
+                "input"  => case.input = unescape(unquote(value)),
+                "output" => case.output = Some(unescape(unquote(value))),
+                _        => {
+                    if !literal::is_number(value) {
+                        return Err(err("expected a number"));
+                    }
+                    let value = literal::number(value,WORD_BITS).map_err(|e| err(&e.message))?;
+                    case.checks.push((key.to_string(),value));
+                }
+            }
+        }
+        Ok(Self {cases})
+    }
+    pub fn load(file : &std::path::Path) -> Result<Self,String> {
+        let src = std::fs::read_to_string(file)
+            .map_err(|e| format!("{}: {}",file.display(),e))?;
+        Self::parse(&src).map_err(|e| format!("{}: {}",file.display(),e))
+    }
+}
+
+/// Cuts off a `;` comment, which can't start inside a quoted value
+fn strip_comment(line : &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i,c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// A value without the double quotes around it, if it has them
+fn unquote(value : &str) -> &str {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) if value.len() >= 2 => inner,
+        _ => value,
+    }
+}
+
+fn unescape(s : &str) -> String {
+    s.replace("\\n","\n").replace("\\t","\t").replace("\\\"","\"")
+}
+
+pub fn run_case(code : &Code, case : &TestCase, config : Config) -> Outcome {
+    //IN reads a line at a time so every character gets its own line
+    let input : String = case.input.chars().flat_map(|c| [c,'\n']).collect();
+    let mut output = Vec::new();
+    let mut machine = Machine::with_io(code.clone(),config,input.as_bytes(),&mut output);
+    if let Err(fault) = machine.run() {
+        return Outcome::Faulted(fault);
+    }
+    let mut failures = Vec::new();
+    for (name,expected) in &case.checks {
+        let actual = match name.as_str() {
+            "ACC" => machine.acc(),
+            "IX"  => machine.ix(),
+             _    => match code.table.lookup(name) {
+                 Some(addr) if !code.table.is_label(name) => match machine.memory().get(addr as usize) {
+                     Some(value) => value,
+                     None => {
+                         failures.push(format!("{} is at address {} which is outside memory",name,addr));
+                         continue;
+                     }
+                 },
+                 _ => {
+                     failures.push(format!("{} is not a variable in the program",name));
+                     continue;
+                 }
+             },
+        };
+        if actual != *expected as i16 {
+            failures.push(format!("expected {} = {} but it was {}",name,expected,actual));
+        }
+    }
+    if let Some(expected) = &case.output {
+        let actual = String::from_utf8_lossy(&output);
+        if actual != *expected {
+            failures.push(format!("expected output {:?} but got {:?}",expected,actual));
+        }
+    }
+    if failures.is_empty() {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(failures.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(src : &str) -> TestCase {
+        Spec::parse(&format!("[case]\n{}",src)).unwrap().cases.remove(0)
+    }
+
+    fn passes(program : &str, spec : &str) -> bool {
+        let code = crate::assemble(program).unwrap();
+        matches!(run_case(&code,&case(spec),Config::default()),Outcome::Passed)
+    }
+
+    #[test]
+    fn values_are_written_like_in_a_program() {
+        let checks = case("X = 65535\nY = -1\nZ = &FF ; a comment").checks;
+        assert_eq!(checks,vec![("X".to_string(),65535),("Y".to_string(),-1),("Z".to_string(),255)]);
+        assert!(Spec::parse("[case]\nX = 65536").is_err());
+        assert!(Spec::parse("[case]\nX = ten").is_err());
+    }
+
+    #[test]
+    fn unsigned_value_matches_the_word() {
+        let program = "LDM #-1\nSTO X\nEND\nX: #0";
+        assert!(passes(program,"X = 65535"));
+        assert!(passes(program,"X = -1"));
+        assert!(!passes(program,"X = 65534"));
+    }
+
+    #[test]
+    fn semicolon_in_quotes_is_not_a_comment() {
+        assert_eq!(case("output = \"A; B \" ; a comment").output.as_deref(),Some("A; B "));
+        assert_eq!(case("output = \"say \\\"hi;\\\"\"").output.as_deref(),Some("say \"hi;\""));
+        assert_eq!(case("input = ab ; a comment").input,"ab");
+    }
+}
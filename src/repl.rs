@@ -0,0 +1,260 @@
+//! Typing in instructions one at a time and seeing what each one does straight away.
+//!
+//! Every line typed is assembled against the symbols seen so far and executed on a CPU that
+//! persists between lines. Labels can be defined and then jumped back to, which runs the lines
+//! typed since then again.
+use std::{
+    io::{self,Stdin,Stdout,Write},
+    path::Path,
+};
+use rasm::{
+    build_code,
+    tooling::{source,Computer,ComputerBuilder,CPU,str_to_const,str_to_data,str_to_instr},
+    DisplayStyle,Fault,Instruction,Memory,SymbolTable,DEFAULT_MAX_STEPS,MEMORY_SIZE,
+};
+use crate::cli::ReplArgs;
+
+const HELP : &str =
+r"Type an instruction to run it, or a label ending in ':' to define it for the next one.
+Variables are made the first time they are used as an operand, or declared with values like 'X: #5'.
+Constants are defined like 'MAX EQU 10' and used as '#MAX'.
+    :mem         show every variable
+    :reset       forget everything and start again
+    :load <file> run a program, then carry on typing after it using its labels and variables
+    :help        show this
+    :quit        leave";
+
+struct Repl {
+    style : DisplayStyle,
+    table : SymbolTable,
+    code : Vec<Instruction>,
+    /// The text of every instruction in `code`
+    lines : Vec<String>,
+    computer : Computer<Stdin,Stdout,MEMORY_SIZE>,
+}
+
+pub fn run(args : ReplArgs) -> bool {
+    let mut repl = Repl::new(args.style);
+    println!("rasm {}, type :help for help",env!("CARGO_PKG_VERSION"));
+    if let Some(file) = &args.file {
+        repl.load(file);
+    }
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                return true
+            },
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("{}",e);
+                return false
+            }
+        }
+        let line = line.trim();
+        let (command,arg) = line.split_once(char::is_whitespace).unwrap_or((line,""));
+        match command {
+            ""  => (),
+            ":q" | ":quit" => return true,
+            ":help"  => println!("{}",HELP),
+            ":mem"   => repl.show_memory(),
+            ":reset" => {
+                repl = Repl::new(repl.style);
+                println!("Everything has been reset");
+            },
+            ":load" if !arg.trim().is_empty() => repl.load(Path::new(arg.trim())),
+            ":load" => println!(":load needs a file"),
+            c if c.starts_with(':') => println!("Unknown command '{}', type :help for help",c),
+            _ => repl.enter(line),
+        }
+    }
+}
+
+impl Repl {
+    fn new(style : DisplayStyle) -> Self {
+        let cpu = CPU::new(Memory::new(Vec::new(),style),io::stdout(),io::stdin());
+        Self {
+            style,
+            table : SymbolTable::new(),
+            code : Vec::new(),
+            lines : Vec::new(),
+            computer : ComputerBuilder::new().attach_cpu(cpu).display_style(style).build().unwrap(),
+        }
+    }
+
+    /// Assembles a typed line and runs it
+    fn enter(&mut self, text : &str) {
+        if self.computer.cpu.done() {
+            println!("The program has ended, type :reset to start again");
+            return;
+        }
+        let line = source::Line::parse(text);
+        if let Some(name) = line.label.filter(|_| line.is_data()) {
+            self.declare(name,line.code);
+            return;
+        }
+        if let Some((name,value)) = source::constant(line.code) {
+            match str_to_const(&self.table,value).map_err(|e| e.message).and_then(|v| self.table.add_const(name.to_string(),v).map(|_| v)) {
+                Ok(value) => println!("{} is {}",name,value),
+                Err(message) => println!("{}",message),
+            }
+            return;
+        }
+        if source::org(line.code).is_some() {
+            println!("ORG can't be used here as instructions typed go one after another, put it in a file and :load it");
+            return;
+        }
+        if let Some(label) = line.label {
+            self.table.add_label(label.to_string(),self.code.len(),self.code.len());
+            println!("{} is instruction {}",label,self.code.len());
+            if line.code.is_empty() {
+                return;
+            }
+        }
+        //a jump to a name that isn't a label yet would otherwise make it a variable
+        let mnemonic = line.code.chars().take_while(|c| c.is_alphabetic()).collect::<String>().to_ascii_uppercase();
+        let operand = line.code[mnemonic.len()..].trim();
+        if matches!(mnemonic.as_str(),"JMP" | "JPE" | "JPN")
+            && source::is_name(operand) && !self.table.is_label(operand) {
+            println!("'{}' is not a label, define it by typing '{}:' before the instructions to jump to",operand,operand);
+            return;
+        }
+        match str_to_instr(&mut self.table,line.code) {
+            Ok(instr) => {
+                self.code.push(instr);
+                self.lines.push(line.code.to_string());
+                self.resume();
+            },
+            Err(message) => println!("{}",message),
+        }
+    }
+
+    /// Reserves memory for a typed data declaration and fills it
+    fn declare(&mut self, name : &str, data : &str) {
+        let values = match str_to_data(&self.table,data) {
+            Ok(values) if values.is_empty() => return println!("'{}' needs at least one value",name),
+            Ok(values) => values,
+            Err(message) => return println!("{}",message),
+        };
+        if self.table.num_vars + values.len() > MEMORY_SIZE {
+            return println!("'{}' does not fit in memory, which has {} cells",name,MEMORY_SIZE);
+        }
+        match self.table.add_data(name.to_string(),values.len()) {
+            Ok(addr) => {
+                let cells : Vec<(u16,i16)> = values.into_iter().enumerate().map(|(i,v)| ((addr + i) as u16,v)).collect();
+                self.computer.cpu.memory.load(&cells);
+                println!("{} is address {}",name,addr);
+            },
+            Err(message) => println!("{}",message),
+        }
+    }
+
+    /// Runs from the PC until it reaches the end of what has been typed, then shows the registers
+    fn resume(&mut self) {
+        self.run();
+        println!("{}",self.computer);
+        if self.computer.cpu.done() {
+            println!("The program has ended, type :reset to start again");
+        } else if self.computer.cpu.pc() as usize > self.code.len() {
+            println!("PC is past the instructions typed so far, type :reset to start again");
+        }
+    }
+
+    /// Runs from the PC until it reaches END or the end of the instructions
+    fn run(&mut self) {
+        let mut steps = 0;
+        while (self.computer.cpu.pc() as usize) < self.code.len() && !self.computer.cpu.done() {
+            let pc = self.computer.cpu.pc() as usize;
+            if steps == DEFAULT_MAX_STEPS {
+                println!("Stopped at '{}': {}",self.lines[pc],Fault::StepLimit(steps));
+                break;
+            }
+            steps += 1;
+            if let Err(fault) = self.computer.cpu.execute(&self.code[pc]) {
+                println!("'{}' failed: {}",self.lines[pc],fault);
+                break;
+            }
+        }
+    }
+
+    /// Replaces everything with a program from a file and runs it. Lines typed afterwards go after
+    /// the program, which can be jumped back into
+    fn load(&mut self, file : &Path) {
+        let program = match build_code(file) {
+            Ok(program) => program,
+            Err(diagnostics) => {
+                for d in diagnostics.iter() {
+                    println!("{}: {}",file.display(),d);
+                }
+                return;
+            }
+        };
+        *self = Repl::new(self.style);
+        self.table = program.symbols().clone();
+        self.computer.cpu.memory.load(&program.data);
+        self.computer.cpu.set_pc(program.start() as u16);
+        let instructions = (0..program.code.len()).filter(|&pc| !program.is_gap(pc)).count();
+        self.lines = program.debug_info.into_iter().map(|info| info.text).collect();
+        self.code = program.code;
+        println!("Loaded {} instructions from {}",instructions,file.display());
+        self.run();
+        println!("{}",self.computer);
+        self.computer.cpu.clear_done();
+        self.computer.cpu.set_pc(self.code.len() as u16);
+        println!("Lines typed now go after the program and can use its labels and variables");
+    }
+
+    fn show_memory(&self) {
+        let mut vars : Vec<(&str,usize)> = self.table.vars().collect();
+        if vars.is_empty() {
+            println!("There are no variables yet, one is made the first time a name is used as an operand");
+            return;
+        }
+        vars.sort_by_key(|&(_,addr)| addr);
+        let width = vars.iter().map(|(name,_)| name.len()).max().unwrap_or(0);
+        for (name,addr) in vars {
+            let value = self.computer.cpu.memory.get(addr).unwrap_or(0);
+            println!("{:>3}  {:<width$}  {}",addr,name,self.style.format(value),width = width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A REPL with `src` loaded from a file
+    fn loaded(name : &str, src : &str) -> Repl {
+        let file = std::env::temp_dir().join(format!("rasm-repl-{}-{}.rasm",std::process::id(),name));
+        std::fs::write(&file,src).unwrap();
+        let mut repl = Repl::new(DisplayStyle::Denary);
+        repl.load(&file);
+        std::fs::remove_file(&file).unwrap();
+        repl
+    }
+
+    #[test]
+    fn typing_after_load() {
+        let mut repl = loaded("typing","LDD N\nEND\nN: #9");
+        assert_eq!(repl.computer.cpu.acc(),9);
+        assert!(!repl.computer.cpu.done());
+        repl.enter("X: #3");
+        assert!(repl.table.lookup("X").is_some());
+        repl.enter("ADD X");
+        assert_eq!(repl.computer.cpu.acc(),12);
+    }
+
+    #[test]
+    fn jumping_to_a_loaded_label() {
+        let mut repl = loaded("label","LDM #1\nEND\nSEVEN: LDM #7\nEND");
+        assert_eq!(repl.computer.cpu.acc(),1);
+        repl.enter("JMP SEVEN");
+        assert_eq!(repl.code.len(),5);
+        assert_eq!(repl.computer.cpu.acc(),7);
+        assert!(repl.computer.cpu.done());
+    }
+}
@@ -0,0 +1,111 @@
+//! Stepping through a program one instruction at a time on the terminal.
+use std::io::{self,IsTerminal,Read,Write};
+use rasm::{tooling::{Word,View},DisplayStyle,Machine};
+use crate::{cli::RunArgs,commands::load};
+
+pub fn run(args : RunArgs) -> bool {
+    let code = match load(&args.source) {
+        Some(code) => code,
+        None => return false,
+    };
+    let mut machine = Machine::new(code,args.config);
+    let mut check_input = true;
+    let mut style = args.config.style;
+    let highlight = io::stdout().is_terminal();
+    'main : while !machine.done() {
+        if !machine.mid_cycle() {
+            if let Some((_,info)) = machine.current() {
+                //instructions that aren't on a line of the program itself say where they are from
+                let from = match (machine.program().macro_call(machine.pc() as usize),&info.file) {
+                    (Some(call),Some(_)) => format!("   (from the macro call {}, {})",call,info.location()),
+                    (Some(call),None)    => format!("   (from the macro call {})",call),
+                    (None,Some(_))       => format!("   ({})",info.location()),
+                    (None,None)          => String::new(),
+                };
+                println!("-----------    Instruction Executing : {}{}   ------------",info.text,from);
+            }
+        }
+        let result = if args.fde {
+            machine.micro_step().map(|transfers| {
+                for transfer in transfers {
+                    println!("{}",transfer);
+                }
+                Some(registers(&machine,style))
+            })
+        } else if args.explain {
+            machine.step_explained().map(Some)
+        } else {
+            machine.step().map(|_| None)
+        };
+        match result {
+            Ok(Some(explanation)) => println!("{}",explanation),
+            Ok(None) => (),
+            Err(fault) => {
+                println!("Program stopped on {}: {}",machine.location(),fault);
+                return false
+            }
+        }
+
+        if !machine.mid_cycle() {
+            show(&machine,&args,style,highlight);
+        }
+        if check_input {
+            'input : loop {
+                let mut buf = String::new();
+                println!(r"
+Press:
+   enter: to step 1 {}
+   q to quit
+   c to continue until end
+   d, b, x or a to show values in denary, binary, hex or all of them",if args.fde {"part of the cycle"} else {"instruction"});
+                io::stdin().read_line(&mut buf).unwrap();
+                if buf.trim().is_empty() {
+                    break 'input
+                }
+                match buf.chars().next().unwrap().to_ascii_lowercase() {
+                    'q' => break 'main,
+                    'c' => {check_input = false; break 'input},
+                    c @ ('d' | 'b' | 'x' | 'a') => {
+                        style = match c {
+                            'd' => DisplayStyle::Denary,
+                            'b' => DisplayStyle::Binary,
+                            'x' => DisplayStyle::Hex,
+                            _   => DisplayStyle::All,
+                        };
+                        machine.set_style(style);
+                        show(&machine,&args,style,highlight);
+                    },
+                    _  => println!("Unrecognized command"),
+                };
+            }
+        }
+    }
+    if machine.done() {
+        println!("-------Program ending---------");
+    }
+    true
+}
+
+/// The CPU and the memory the program uses
+fn show<I : Read,O : Write>(machine : &Machine<I,O>, args : &RunArgs, style : DisplayStyle, highlight : bool) {
+    println!("{}",machine);
+    let view = View::new(machine.memory(),machine.program().symbols(),style)
+        .changed(machine.stored().map(usize::from))
+        .highlight(highlight);
+    match &args.memory {
+        Some(range) => println!("{}",view.range(range.clone())),
+        None        => println!("{}",view),
+    }
+}
+
+/// The registers the fetch-decode-execute cycle goes through, on one line
+fn registers<I : Read,O : Write>(machine : &Machine<I,O>, style : DisplayStyle) -> String {
+    let cpu = machine.cpu();
+    let mdr = match cpu.mdr() {
+        Word::Data(value)  => style.format(*value),
+        Word::Instr(instr) => instr.to_string(),
+    };
+    let cir = cpu.cir().map_or(String::new(),|instr| instr.to_string());
+    format!("         PC: {}  MAR: {}  MDR: {}  CIR: {}  ACC: {}  IX: {}",
+        cpu.pc(),cpu.mar(),mdr,cir,style.format(cpu.acc()),style.format(cpu.ix()))
+}